@@ -0,0 +1,63 @@
+use std::{env, str::FromStr, time::Duration};
+
+use anchor_spl::token::spl_token::native_mint;
+use anyhow::{anyhow, bail, Context, Result};
+use solana_sdk::pubkey::Pubkey;
+
+pub struct Config {
+    pub rpc_url: String,
+    pub keypair_path: String,
+    pub sol_mint: Pubkey,
+    pub usdc_mint: Pubkey,
+    pub sol_price_update: Pubkey,
+    pub usdc_price_update: Pubkey,
+    pub poll_interval: Duration,
+    pub once: bool,
+}
+
+impl Config {
+    pub fn from_args() -> Result<Self> {
+        let mut rpc_url = "http://127.0.0.1:8899".to_string();
+        let mut keypair_path = format!("{}/.config/solana/id.json", env::var("HOME")?);
+        let mut sol_mint = native_mint::ID;
+        let mut usdc_mint = None;
+        let mut sol_price_update = None;
+        let mut usdc_price_update = None;
+        let mut poll_interval = Duration::from_secs(10);
+        let mut once = false;
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--rpc-url" => rpc_url = value()?,
+                "--keypair" => keypair_path = value()?,
+                "--sol-mint" => sol_mint = parse_pubkey(&value()?)?,
+                "--usdc-mint" => usdc_mint = Some(parse_pubkey(&value()?)?),
+                "--sol-price-update" => sol_price_update = Some(parse_pubkey(&value()?)?),
+                "--usdc-price-update" => usdc_price_update = Some(parse_pubkey(&value()?)?),
+                "--interval" => poll_interval = Duration::from_secs(value()?.parse()?),
+                "--once" => once = true,
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+
+        Ok(Self {
+            rpc_url,
+            keypair_path,
+            sol_mint,
+            usdc_mint: usdc_mint.context("--usdc-mint is required")?,
+            sol_price_update: sol_price_update.context("--sol-price-update is required")?,
+            usdc_price_update: usdc_price_update.context("--usdc-price-update is required")?,
+            poll_interval,
+            once,
+        })
+    }
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|err| anyhow!("invalid pubkey {value}: {err}"))
+}
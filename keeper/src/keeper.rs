@@ -0,0 +1,639 @@
+use anchor_lang::{
+    prelude::{AccountMeta, Clock},
+    solana_program::system_program,
+    InstructionData, ToAccountMetas,
+};
+use anchor_spl::associated_token::{self, get_associated_token_address_with_program_id};
+use anyhow::{bail, Result};
+use lending::{
+    Bank, User, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT, SEED_COLLATERAL_VAULT,
+    SEED_EMODE_CATEGORY, SEED_FEE_VAULT, SEED_FIXED_BORROW, SEED_INSURANCE_VAULT,
+    SEED_TREASURY_ACCOUNT, SEED_USER_REWARD,
+};
+use pyth_solana_receiver_sdk::price_update::Price;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signature};
+
+use crate::{config::Config, rpc::LendingRpc};
+
+pub struct Market {
+    pub clock: Clock,
+    pub sol_bank: Bank,
+    pub usdc_bank: Bank,
+    pub sol_price: Price,
+    pub usdc_price: Price,
+}
+
+pub struct Candidate {
+    pub user: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub borrowed_mint: Pubkey,
+    pub emode_category: Option<Pubkey>,
+    /// Reward pairs for the borrowed bank, then the collateral bank.
+    pub reward_accounts: Vec<AccountMeta>,
+}
+
+pub struct Keeper<R> {
+    rpc: R,
+    config: Config,
+}
+
+impl<R: LendingRpc> Keeper<R> {
+    pub fn new(rpc: R, config: Config) -> Self {
+        Self { rpc, config }
+    }
+
+    pub fn run_once(&self) -> Result<Vec<Signature>> {
+        let market = self.fetch_market()?;
+        let mut signatures = Vec::new();
+
+        for (address, user) in self.rpc.get_users()? {
+            let Some(candidate) = self.check_user(address, &user, &market)? else {
+                continue;
+            };
+
+            match self.liquidate(&candidate) {
+                Ok(signature) => {
+                    println!("liquidated {address}: {signature}");
+                    signatures.push(signature);
+                }
+                Err(err) => eprintln!("failed to liquidate {address}: {err}"),
+            }
+        }
+
+        Ok(signatures)
+    }
+
+    /// Loads both banks accrued to the current clock, as `refresh_user` sees them.
+    pub fn fetch_market(&self) -> Result<Market> {
+        let clock = self.rpc.get_clock()?;
+
+        let mut sol_bank = self.rpc.get_bank(&bank_address(&self.config.sol_mint))?;
+        let mut usdc_bank = self.rpc.get_bank(&bank_address(&self.config.usdc_mint))?;
+        sol_bank.accrue_interest(&clock)?;
+        usdc_bank.accrue_interest(&clock)?;
+
+        let sol_price = sol_bank.price(
+            &self.rpc.get_price_update(&self.config.sol_price_update)?,
+            &clock,
+        )?;
+        let usdc_price = usdc_bank.price(
+            &self.rpc.get_price_update(&self.config.usdc_price_update)?,
+            &clock,
+        )?;
+
+        Ok(Market {
+            clock,
+            sol_bank,
+            usdc_bank,
+            sol_price,
+            usdc_price,
+        })
+    }
+
+    /// Runs the program's own refresh math on a copy of the user and, if the
+    /// position is unhealthy, picks its largest collateral and debt legs.
+    pub fn check_user(
+        &self,
+        address: Pubkey,
+        user: &User,
+        market: &Market,
+    ) -> Result<Option<Candidate>> {
+        if user.mint_usdc != self.config.usdc_mint
+            || user.mint_sol != self.config.sol_mint
+            || (user.borrowed_sol_shares == 0 && user.borrowed_usdc_shares == 0)
+        {
+            return Ok(None);
+        }
+
+        let mut user = user.clone();
+        let mut positions = Vec::new();
+        let mut collateral = (0, self.config.sol_mint);
+        let mut debt = (0, self.config.sol_mint);
+
+        for (bank, price) in [
+            (&market.sol_bank, &market.sol_price),
+            (&market.usdc_bank, &market.usdc_price),
+        ] {
+            // Price fixed-rate debt at what it owes, as the program will.
+            let mut bank = bank.clone();
+            let fixed_borrow = fixed_borrow_address(&address, &bank.mint);
+            if let Some(mut position) = self.rpc.get_fixed_borrow(&fixed_borrow)? {
+                position.settle(&mut bank, &mut user, market.clock.unix_timestamp)?;
+            }
+
+            let deposited_shares = user.deposited_shares(&bank.mint);
+            let borrowed_shares = user.borrowed_shares(&bank.mint);
+            if deposited_shares == 0 && borrowed_shares == 0 {
+                continue;
+            }
+
+            let deposit_value =
+                bank.market_value(bank.deposit_amount_for(deposited_shares)?, price)?;
+            let borrow_value =
+                bank.market_value(bank.borrow_amount_for(borrowed_shares)?, price)?;
+            if deposit_value > collateral.0 {
+                collateral = (deposit_value, bank.mint);
+            }
+            if borrow_value > debt.0 {
+                debt = (borrow_value, bank.mint);
+            }
+
+            positions.push((bank, *price));
+        }
+
+        let emode_category = (user.emode_category != 0).then(|| emode_address(user.emode_category));
+        let emode = emode_category
+            .map(|address| self.rpc.get_emode_category(&address))
+            .transpose()?;
+
+        user.refresh(&positions, emode.as_ref(), market.clock.slot)?;
+        if !user.is_liquidatable() {
+            return Ok(None);
+        }
+
+        let mut reward_accounts = Vec::new();
+        for mint in [debt.1, collateral.1] {
+            let bank = if mint == market.usdc_bank.mint {
+                &market.usdc_bank
+            } else {
+                &market.sol_bank
+            };
+            for pool in bank.reward_pools() {
+                reward_accounts.push(AccountMeta::new(*pool, false));
+                reward_accounts.push(AccountMeta::new(user_reward_address(pool, &address), false));
+            }
+        }
+
+        Ok(Some(Candidate {
+            user: address,
+            collateral_mint: collateral.1,
+            borrowed_mint: debt.1,
+            emode_category,
+            reward_accounts,
+        }))
+    }
+
+    /// Liquidates as much of the candidate's debt as the payer's wallet can
+    /// repay. `liquidate` refreshes the position itself, so it goes alone.
+    /// There is no flash-loan funding: the program has no flash-loan
+    /// instruction to borrow the repayment through, so the payer must hold
+    /// the borrowed mint.
+    pub fn liquidate(&self, candidate: &Candidate) -> Result<Signature> {
+        let instruction = self.liquidate_instruction(candidate)?;
+        self.rpc.send_instructions(&[instruction])
+    }
+
+    pub fn liquidate_instruction(&self, candidate: &Candidate) -> Result<Instruction> {
+        let liquidator = self.rpc.payer();
+        let token_program = self.rpc.get_token_program(&candidate.collateral_mint)?;
+        let borrowed_token_program = self.rpc.get_token_program(&candidate.borrowed_mint)?;
+        // `liquidate` moves both mints through a single token program.
+        if borrowed_token_program != token_program {
+            bail!(
+                "{} and {} use different token programs",
+                candidate.collateral_mint,
+                candidate.borrowed_mint
+            );
+        }
+
+        let liquidator_borrowed_ata = get_associated_token_address_with_program_id(
+            &liquidator,
+            &candidate.borrowed_mint,
+            &borrowed_token_program,
+        );
+        let amount = self.rpc.get_token_balance(&liquidator_borrowed_ata)?;
+        if amount == 0 {
+            bail!("no {} to repay with", candidate.borrowed_mint);
+        }
+
+        let accounts = lending::accounts::Liquidate {
+            liquidator,
+            collateral_mint: candidate.collateral_mint,
+            borrowed_mint: candidate.borrowed_mint,
+            collateral_bank: bank_address(&candidate.collateral_mint),
+            borrowed_bank: bank_address(&candidate.borrowed_mint),
+            collateral_treasury: treasury_address(&candidate.collateral_mint),
+            borrowed_treasury: treasury_address(&candidate.borrowed_mint),
+            collateral_receipt_mint: lending_address(
+                SEED_COLLATERAL_MINT,
+                &candidate.collateral_mint,
+            ),
+            collateral_receipt_vault: lending_address(
+                SEED_COLLATERAL_VAULT,
+                &candidate.collateral_mint,
+            ),
+            fee_vault: lending_address(SEED_FEE_VAULT, &candidate.collateral_mint),
+            insurance_vault: lending_address(SEED_INSURANCE_VAULT, &candidate.collateral_mint),
+            user: candidate.user,
+            fixed_borrow: fixed_borrow_address(&candidate.user, &candidate.borrowed_mint),
+            collateral_fixed_borrow: fixed_borrow_address(
+                &candidate.user,
+                &candidate.collateral_mint,
+            ),
+            emode_category: candidate.emode_category,
+            collateral_price_update: self.price_update_for(&candidate.collateral_mint),
+            borrowed_price_update: self.price_update_for(&candidate.borrowed_mint),
+            liquidator_collateral_ata: get_associated_token_address_with_program_id(
+                &liquidator,
+                &candidate.collateral_mint,
+                &token_program,
+            ),
+            liquidator_borrowed_ata,
+            token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        };
+
+        let mut accounts = accounts.to_account_metas(None);
+        accounts.extend(candidate.reward_accounts.iter().cloned());
+
+        Ok(Instruction {
+            program_id: lending::ID,
+            accounts,
+            data: lending::instruction::Liquidate { amount }.data(),
+        })
+    }
+
+    fn price_update_for(&self, mint: &Pubkey) -> Pubkey {
+        if *mint == self.config.usdc_mint {
+            self.config.usdc_price_update
+        } else {
+            self.config.sol_price_update
+        }
+    }
+}
+
+pub fn bank_address(mint: &Pubkey) -> Pubkey {
+    lending_address(SEED_BANK_ACCOUNT, mint)
+}
+
+pub fn emode_address(category: u8) -> Pubkey {
+    Pubkey::find_program_address(&[SEED_EMODE_CATEGORY, &[category]], &lending::ID).0
+}
+
+pub fn fixed_borrow_address(user: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[SEED_FIXED_BORROW, user.as_ref(), mint.as_ref()],
+        &lending::ID,
+    )
+    .0
+}
+
+pub fn user_reward_address(reward_pool: &Pubkey, user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[SEED_USER_REWARD, reward_pool.as_ref(), user.as_ref()],
+        &lending::ID,
+    )
+    .0
+}
+
+pub fn treasury_address(mint: &Pubkey) -> Pubkey {
+    lending_address(SEED_TREASURY_ACCOUNT, mint)
+}
+
+/// Address of one of a bank's per-mint PDAs.
+fn lending_address(seed: &[u8], mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[seed, mint.as_ref()], &lending::ID).0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, time::Duration};
+
+    use anchor_lang::{AccountDeserialize, Space};
+    use anchor_spl::token;
+    use anyhow::anyhow;
+    use lending::{EmodeCategory, FixedBorrow, SOL_USD_FEED_ID, USDC_USD_FEED_ID};
+    use pyth_solana_receiver_sdk::price_update::{
+        get_feed_id_from_hex, PriceFeedMessage, PriceUpdateV2, VerificationLevel,
+    };
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const SLOT: u64 = 1_000;
+    const YEAR: i64 = 365 * 24 * 60 * 60;
+
+    struct MockRpc {
+        payer: Pubkey,
+        users: Vec<(Pubkey, User)>,
+        banks: HashMap<Pubkey, Bank>,
+        emode_categories: HashMap<Pubkey, EmodeCategory>,
+        fixed_borrows: HashMap<Pubkey, FixedBorrow>,
+        /// Price in 1e-8 dollars and feed id for each price update account.
+        prices: HashMap<Pubkey, (i64, &'static str)>,
+        token_balances: HashMap<Pubkey, u64>,
+        sent: RefCell<Vec<Vec<Instruction>>>,
+    }
+
+    impl LendingRpc for MockRpc {
+        fn payer(&self) -> Pubkey {
+            self.payer
+        }
+
+        fn get_users(&self) -> Result<Vec<(Pubkey, User)>> {
+            Ok(self.users.clone())
+        }
+
+        fn get_bank(&self, bank: &Pubkey) -> Result<Bank> {
+            self.banks
+                .get(bank)
+                .cloned()
+                .ok_or_else(|| anyhow!("no bank {bank}"))
+        }
+
+        fn get_emode_category(&self, emode_category: &Pubkey) -> Result<EmodeCategory> {
+            self.emode_categories
+                .get(emode_category)
+                .cloned()
+                .ok_or_else(|| anyhow!("no e-mode category {emode_category}"))
+        }
+
+        fn get_fixed_borrow(&self, fixed_borrow: &Pubkey) -> Result<Option<FixedBorrow>> {
+            Ok(self.fixed_borrows.get(fixed_borrow).cloned())
+        }
+
+        fn get_price_update(&self, price_update: &Pubkey) -> Result<PriceUpdateV2> {
+            let (price, feed_id) = self
+                .prices
+                .get(price_update)
+                .ok_or_else(|| anyhow!("no price update {price_update}"))?;
+
+            Ok(PriceUpdateV2 {
+                write_authority: Pubkey::default(),
+                verification_level: VerificationLevel::Full,
+                price_message: PriceFeedMessage {
+                    feed_id: get_feed_id_from_hex(feed_id)
+                        .map_err(anchor_lang::error::Error::from)?,
+                    price: *price,
+                    conf: 0,
+                    exponent: -8,
+                    publish_time: NOW,
+                    prev_publish_time: NOW,
+                    ema_price: *price,
+                    ema_conf: 0,
+                },
+                posted_slot: SLOT,
+            })
+        }
+
+        fn get_token_program(&self, _mint: &Pubkey) -> Result<Pubkey> {
+            Ok(token::ID)
+        }
+
+        fn get_token_balance(&self, token_account: &Pubkey) -> Result<u64> {
+            Ok(self
+                .token_balances
+                .get(token_account)
+                .copied()
+                .unwrap_or_default())
+        }
+
+        fn get_clock(&self) -> Result<Clock> {
+            Ok(Clock {
+                slot: SLOT,
+                unix_timestamp: NOW,
+                ..Default::default()
+            })
+        }
+
+        fn send_instructions(&self, instructions: &[Instruction]) -> Result<Signature> {
+            self.sent.borrow_mut().push(instructions.to_vec());
+            Ok(Signature::default())
+        }
+    }
+
+    /// An account with every field zeroed, to fill in field by field.
+    fn zeroed<T: AccountDeserialize + Space>() -> T {
+        let data = vec![0; 8 + T::INIT_SPACE];
+        T::try_deserialize_unchecked(&mut data.as_slice()).unwrap()
+    }
+
+    fn bank(mint: Pubkey, feed_id: &str, decimals: u8, supply: u64, borrowed: u64) -> Bank {
+        let mut bank: Bank = zeroed();
+        bank.mint = mint;
+        bank.oracle_feed_id = get_feed_id_from_hex(feed_id).unwrap();
+        bank.mint_decimals = decimals;
+        bank.total_deposit = supply;
+        bank.total_deposit_shares = supply;
+        bank.total_borrowed = borrowed;
+        bank.total_borrowed_shares = borrowed;
+        bank.liquidation_threshold = 8000;
+        bank.max_ltv = 7500;
+        bank.liquidation_close_factor = 5000;
+        bank.interest_rate = 500;
+        bank.last_updated = NOW;
+        bank.last_updated_slot = SLOT;
+        bank
+    }
+
+    /// One SOL of collateral, worth $100, against `usdc_debt` micro-USDC.
+    fn user(config: &Config, usdc_debt: u64) -> User {
+        let mut user: User = zeroed();
+        user.owner = Pubkey::new_unique();
+        user.mint_usdc = config.usdc_mint;
+        user.mint_sol = config.sol_mint;
+        user.deposited_sol = 1_000_000_000;
+        user.deposited_sol_shares = 1_000_000_000;
+        user.borrowed_usdc = usdc_debt;
+        user.borrowed_usdc_shares = usdc_debt;
+        user
+    }
+
+    fn config() -> Config {
+        Config {
+            rpc_url: String::new(),
+            keypair_path: String::new(),
+            sol_mint: Pubkey::new_unique(),
+            usdc_mint: Pubkey::new_unique(),
+            sol_price_update: Pubkey::new_unique(),
+            usdc_price_update: Pubkey::new_unique(),
+            poll_interval: Duration::from_secs(1),
+            once: true,
+        }
+    }
+
+    fn keeper(config: Config, users: Vec<(Pubkey, User)>) -> Keeper<MockRpc> {
+        let banks = HashMap::from([
+            (
+                bank_address(&config.sol_mint),
+                bank(config.sol_mint, SOL_USD_FEED_ID, 9, 1_000_000_000_000, 0),
+            ),
+            (
+                bank_address(&config.usdc_mint),
+                bank(
+                    config.usdc_mint,
+                    USDC_USD_FEED_ID,
+                    6,
+                    1_000_000_000,
+                    100_000_000,
+                ),
+            ),
+        ]);
+        let prices = HashMap::from([
+            (config.sol_price_update, (10_000_000_000, SOL_USD_FEED_ID)),
+            (config.usdc_price_update, (100_000_000, USDC_USD_FEED_ID)),
+        ]);
+        // Enough USDC to repay any of the test positions.
+        let payer = Pubkey::new_unique();
+        let token_balances = HashMap::from([(
+            get_associated_token_address_with_program_id(&payer, &config.usdc_mint, &token::ID),
+            1_000_000_000,
+        )]);
+        let rpc = MockRpc {
+            payer,
+            users,
+            banks,
+            emode_categories: HashMap::new(),
+            fixed_borrows: HashMap::new(),
+            prices,
+            token_balances,
+            sent: RefCell::new(Vec::new()),
+        };
+
+        Keeper::new(rpc, config)
+    }
+
+    #[test]
+    fn healthy_user_is_left_alone() {
+        let config = config();
+        let address = Pubkey::new_unique();
+        let user = user(&config, 50_000_000);
+        let keeper = keeper(config, vec![(address, user.clone())]);
+
+        let market = keeper.fetch_market().unwrap();
+        assert!(keeper
+            .check_user(address, &user, &market)
+            .unwrap()
+            .is_none());
+
+        assert!(keeper.run_once().unwrap().is_empty());
+        assert!(keeper.rpc.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn unhealthy_user_is_liquidated() {
+        let config = config();
+        let (sol_mint, usdc_mint) = (config.sol_mint, config.usdc_mint);
+        let address = Pubkey::new_unique();
+        let user = user(&config, 90_000_000);
+        let keeper = keeper(config, vec![(address, user.clone())]);
+
+        let market = keeper.fetch_market().unwrap();
+        let candidate = keeper.check_user(address, &user, &market).unwrap().unwrap();
+        assert_eq!(candidate.collateral_mint, sol_mint);
+        assert_eq!(candidate.borrowed_mint, usdc_mint);
+
+        assert_eq!(keeper.run_once().unwrap().len(), 1);
+        let sent = keeper.rpc.sent.borrow();
+        let [liquidate] = sent[0].as_slice() else {
+            panic!("expected a lone liquidation, got {:?}", sent[0]);
+        };
+
+        // The whole wallet balance is offered; the program caps it.
+        assert_eq!(
+            liquidate.data,
+            lending::instruction::Liquidate {
+                amount: 1_000_000_000
+            }
+            .data()
+        );
+        let expected = keeper.liquidate_instruction(&candidate).unwrap();
+        assert_eq!(liquidate.accounts, expected.accounts);
+        assert!(liquidate
+            .accounts
+            .iter()
+            .any(|meta| meta.pubkey == fixed_borrow_address(&address, &usdc_mint)));
+    }
+
+    #[test]
+    fn unfunded_liquidations_are_not_sent() {
+        let config = config();
+        let address = Pubkey::new_unique();
+        let user = user(&config, 90_000_000);
+        let mut keeper = keeper(config, vec![(address, user)]);
+        keeper.rpc.token_balances.clear();
+
+        assert!(keeper.run_once().unwrap().is_empty());
+        assert!(keeper.rpc.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn emode_users_are_valued_with_the_category() {
+        let config = config();
+        let address = Pubkey::new_unique();
+        // $85 of debt is past the banks' 80% threshold but within the category's 90%.
+        let mut user = user(&config, 85_000_000);
+        user.emode_category = 1;
+        let mut keeper = keeper(config, vec![(address, user.clone())]);
+        for bank in keeper.rpc.banks.values_mut() {
+            bank.emode_category = 1;
+        }
+        keeper.rpc.emode_categories.insert(
+            emode_address(1),
+            EmodeCategory {
+                id: 1,
+                max_ltv: 8500,
+                liquidation_threshold: 9000,
+                liquidation_bonus: 200,
+                bump: 0,
+            },
+        );
+
+        let market = keeper.fetch_market().unwrap();
+        assert!(keeper
+            .check_user(address, &user, &market)
+            .unwrap()
+            .is_none());
+
+        let user = User {
+            borrowed_usdc: 95_000_000,
+            borrowed_usdc_shares: 95_000_000,
+            ..user
+        };
+        let candidate = keeper.check_user(address, &user, &market).unwrap().unwrap();
+        assert_eq!(candidate.emode_category, Some(emode_address(1)));
+        let liquidate = keeper.liquidate_instruction(&candidate).unwrap();
+        assert!(liquidate
+            .accounts
+            .iter()
+            .any(|meta| meta.pubkey == emode_address(1)));
+    }
+
+    #[test]
+    fn fixed_rate_interest_counts_toward_health() {
+        let config = config();
+        let usdc_mint = config.usdc_mint;
+        let address = Pubkey::new_unique();
+        // $75 of debt is healthy at the variable price, but a year at 20%
+        // fixed makes it $90.
+        let user = user(&config, 75_000_000);
+        let mut keeper = keeper(config, vec![(address, user.clone())]);
+
+        let market = keeper.fetch_market().unwrap();
+        assert!(keeper
+            .check_user(address, &user, &market)
+            .unwrap()
+            .is_none());
+
+        let fixed_borrow = FixedBorrow {
+            user: address,
+            mint: usdc_mint,
+            shares: 75_000_000,
+            amount: 75_000_000,
+            rate: 2000,
+            maturity: NOW + YEAR,
+            last_settled: NOW - YEAR,
+            bump: 0,
+        };
+        keeper
+            .rpc
+            .fixed_borrows
+            .insert(fixed_borrow_address(&address, &usdc_mint), fixed_borrow);
+
+        assert!(keeper
+            .check_user(address, &user, &market)
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,34 @@
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use solana_sdk::signature::read_keypair_file;
+
+mod config;
+mod keeper;
+mod rpc;
+
+use config::Config;
+use keeper::Keeper;
+use rpc::SolanaRpc;
+
+fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    let payer = read_keypair_file(&config.keypair_path)
+        .map_err(|err| anyhow!("failed to read keypair {}: {err}", config.keypair_path))?;
+
+    let rpc = SolanaRpc::new(config.rpc_url.clone(), payer);
+    let poll_interval = config.poll_interval;
+    let once = config.once;
+    let keeper = Keeper::new(rpc, config);
+
+    loop {
+        if let Err(err) = keeper.run_once() {
+            eprintln!("scan failed: {err}");
+        }
+
+        if once {
+            return Ok(());
+        }
+        thread::sleep(poll_interval);
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anchor_lang::{prelude::Clock, AccountDeserialize, Discriminator};
+use anchor_spl::token_interface::TokenAccount;
+use anyhow::Result;
+use lending::{Bank, EmodeCategory, FixedBorrow, User};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+
+/// Everything the keeper needs from the cluster, so a scan can be driven by a
+/// local validator or by an in-memory mock.
+pub trait LendingRpc {
+    fn payer(&self) -> Pubkey;
+    fn get_users(&self) -> Result<Vec<(Pubkey, User)>>;
+    fn get_bank(&self, bank: &Pubkey) -> Result<Bank>;
+    fn get_emode_category(&self, emode_category: &Pubkey) -> Result<EmodeCategory>;
+    /// `None` when no fixed borrow was ever opened at the address.
+    fn get_fixed_borrow(&self, fixed_borrow: &Pubkey) -> Result<Option<FixedBorrow>>;
+    fn get_price_update(&self, price_update: &Pubkey) -> Result<PriceUpdateV2>;
+    fn get_token_program(&self, mint: &Pubkey) -> Result<Pubkey>;
+    /// Zero when the token account does not exist.
+    fn get_token_balance(&self, token_account: &Pubkey) -> Result<u64>;
+    fn get_clock(&self) -> Result<Clock>;
+    fn send_instructions(&self, instructions: &[Instruction]) -> Result<Signature>;
+}
+
+pub struct SolanaRpc {
+    client: RpcClient,
+    payer: Keypair,
+}
+
+impl SolanaRpc {
+    pub fn new(rpc_url: String, payer: Keypair) -> Self {
+        Self {
+            client: RpcClient::new(rpc_url),
+            payer,
+        }
+    }
+
+    fn get_anchor_account<T: AccountDeserialize>(&self, address: &Pubkey) -> Result<T> {
+        let data = self.client.get_account_data(address)?;
+        Ok(T::try_deserialize(&mut data.as_slice())?)
+    }
+}
+
+impl LendingRpc for SolanaRpc {
+    fn payer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    fn get_users(&self) -> Result<Vec<(Pubkey, User)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                0,
+                &User::DISCRIMINATOR,
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        self.client
+            .get_program_accounts_with_config(&lending::ID, config)?
+            .into_iter()
+            .map(|(address, account)| {
+                Ok((
+                    address,
+                    User::try_deserialize(&mut account.data.as_slice())?,
+                ))
+            })
+            .collect()
+    }
+
+    fn get_bank(&self, bank: &Pubkey) -> Result<Bank> {
+        self.get_anchor_account(bank)
+    }
+
+    fn get_emode_category(&self, emode_category: &Pubkey) -> Result<EmodeCategory> {
+        self.get_anchor_account(emode_category)
+    }
+
+    fn get_fixed_borrow(&self, fixed_borrow: &Pubkey) -> Result<Option<FixedBorrow>> {
+        let Some(account) = self
+            .client
+            .get_account_with_commitment(fixed_borrow, self.client.commitment())?
+            .value
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(FixedBorrow::try_deserialize(
+            &mut account.data.as_slice(),
+        )?))
+    }
+
+    fn get_price_update(&self, price_update: &Pubkey) -> Result<PriceUpdateV2> {
+        self.get_anchor_account(price_update)
+    }
+
+    fn get_token_program(&self, mint: &Pubkey) -> Result<Pubkey> {
+        Ok(self.client.get_account(mint)?.owner)
+    }
+
+    fn get_token_balance(&self, token_account: &Pubkey) -> Result<u64> {
+        let Some(account) = self
+            .client
+            .get_account_with_commitment(token_account, self.client.commitment())?
+            .value
+        else {
+            return Ok(0);
+        };
+
+        Ok(TokenAccount::try_deserialize(&mut account.data.as_slice())?.amount)
+    }
+
+    fn get_clock(&self) -> Result<Clock> {
+        let slot = self.client.get_slot()?;
+        let unix_timestamp = match self.client.get_block_time(slot) {
+            Ok(timestamp) => timestamp,
+            Err(_) => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+        };
+
+        Ok(Clock {
+            slot,
+            unix_timestamp,
+            ..Default::default()
+        })
+    }
+
+    fn send_instructions(&self, instructions: &[Instruction]) -> Result<Signature> {
+        let blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            blockhash,
+        );
+
+        Ok(self.client.send_and_confirm_transaction(&transaction)?)
+    }
+}
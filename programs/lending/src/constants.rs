@@ -7,8 +7,42 @@ pub const SOL_USD_FEED_ID: &str =
     "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
 pub const USDC_USD_FEED_ID: &str =
     "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
+pub const SEED_MARKET: &[u8] = b"market";
 pub const SEED_BANK_ACCOUNT: &[u8] = b"bank";
 pub const SEED_USER_ACCOUNT: &[u8] = b"user";
 pub const SEED_TREASURY_ACCOUNT: &[u8] = b"treasury";
+pub const SEED_COLLATERAL_MINT: &[u8] = b"collateral_mint";
+pub const SEED_COLLATERAL_VAULT: &[u8] = b"collateral_vault";
+pub const SEED_EMODE_CATEGORY: &[u8] = b"emode";
+pub const SEED_FEE_VAULT: &[u8] = b"fee_vault";
+pub const SEED_INSURANCE_VAULT: &[u8] = b"insurance_vault";
+pub const SEED_REFERRER: &[u8] = b"referrer";
+pub const SEED_REWARD_POOL: &[u8] = b"reward_pool";
+pub const SEED_REWARD_VAULT: &[u8] = b"reward_vault";
+pub const SEED_USER_REWARD: &[u8] = b"user_reward";
+pub const SEED_FIXED_BORROW: &[u8] = b"fixed_borrow";
+pub const SEED_WSOL_ACCOUNT: &[u8] = b"wsol";
 
 pub const MAX_AGE: u64 = 100;
+
+pub const PERMISSION_DEPOSIT: u8 = 1 << 0;
+pub const PERMISSION_REPAY: u8 = 1 << 1;
+pub const PERMISSION_WITHDRAW: u8 = 1 << 2;
+pub const PERMISSION_BORROW: u8 = 1 << 3;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+pub const VALUE_DECIMALS: u32 = 6;
+pub const REWARD_INDEX_SCALE: u128 = 1_000_000_000_000;
+
+/// Debt worth less than this after a liquidation (in `VALUE_DECIMALS`) may be
+/// closed in full, past the close factor, so no unliquidatable dust remains.
+pub const LIQUIDATION_DUST_VALUE: u64 = 1_000_000;
+
+/// Seconds between an authority requesting an insurance withdrawal and being
+/// able to execute it.
+pub const INSURANCE_WITHDRAWAL_TIMELOCK: i64 = 7 * 24 * 60 * 60;
+
+pub const MAX_TRANSFER_HOOK_PROGRAMS: usize = 4;
+
+pub const MAX_REWARD_POOLS: usize = 4;
@@ -1,15 +1,15 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
 };
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
 use crate::{
-    error::ErrorCode, Bank, User, MAX_AGE, SEED_BANK_ACCOUNT, SEED_TREASURY_ACCOUNT,
-    SEED_USER_ACCOUNT, SOL_USD_FEED_ID, USDC_USD_FEED_ID,
+    accrue_rewards, apply_bps, error::ErrorCode, settle_rewards, transfer_checked_with_hook,
+    unwrap_sol, Bank, FixedBorrow, Referrer, RiskTier, User, ANCHOR_DISCRIMINATOR,
+    PERMISSION_BORROW, SEED_BANK_ACCOUNT, SEED_FIXED_BORROW, SEED_REFERRER, SEED_TREASURY_ACCOUNT,
+    SEED_USER_ACCOUNT, SEED_WSOL_ACCOUNT,
 };
 
 #[derive(Accounts)]
@@ -23,6 +23,14 @@ pub struct Borrow<'info> {
         bump = bank.bump,
     )]
     bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, collateral_bank.mint.as_ref()],
+        bump = collateral_bank.bump,
+        constraint = collateral_bank.mint != mint.key() @ ErrorCode::InvalidCollateralBank,
+        constraint = user.is_pair(&collateral_bank.mint, &mint.key()) @ ErrorCode::InvalidCollateralBank,
+    )]
+    collateral_bank: Account<'info, Bank>,
     #[account(
         mut,
         seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
@@ -34,15 +42,29 @@ pub struct Borrow<'info> {
     treasury: InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
-        seeds = [SEED_USER_ACCOUNT, signer.key().as_ref()],
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
         bump = user.bump,
+        constraint = user.can_act(&signer.key(), PERMISSION_BORROW) @ ErrorCode::NotDelegated,
     )]
     user: Account<'info, User>,
+    #[account(
+        mut,
+        seeds = [SEED_REFERRER, referrer.owner.as_ref(), mint.key().as_ref()],
+        bump = referrer.bump,
+    )]
+    referrer: Option<Account<'info, Referrer>>,
+    /// CHECK: only used as the payout ATA authority, pinned to `user.owner`.
+    #[account(address = user.owner)]
+    owner: UncheckedAccount<'info>,
     #[account(
         init_if_needed,
         payer = signer,
         associated_token::mint = mint,
-        associated_token::authority = signer,
+        associated_token::authority = owner,
         associated_token::token_program = token_program
     )]
     user_ata: InterfaceAccount<'info, TokenAccount>,
@@ -54,48 +76,59 @@ pub struct Borrow<'info> {
 }
 
 impl<'info> Borrow<'info> {
-    pub fn borrow(&mut self, amount: u64) -> Result<()> {
-        let total_collateral: u64;
-
-        match self.mint.to_account_info().key() {
-            key if key == self.user.mint_usdc => {
-                let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)?;
-                let sol_price = self.price_update.get_price_no_older_than(
-                    &Clock::get()?,
-                    MAX_AGE,
-                    &sol_feed_id,
-                )?;
-                let new_value = self.calculate_accrued_interest(
-                    self.user.deposited_sol,
-                    self.bank.interest_rate,
-                    self.user.last_updated,
-                )?;
-                total_collateral = sol_price.price as u64 * new_value;
-            }
-            _ => {
-                let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)?;
-                let usdc_price = self.price_update.get_price_no_older_than(
-                    &Clock::get()?,
-                    MAX_AGE,
-                    &usdc_feed_id,
-                )?;
-                let new_value = self.calculate_accrued_interest(
-                    self.user.deposited_usdc,
-                    self.bank.interest_rate,
-                    self.user.last_updated,
-                )?;
-                total_collateral = usdc_price.price as u64 * new_value;
-            }
+    pub fn borrow(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, remaining_accounts) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        self.draw(amount, remaining_accounts, &clock)?;
+
+        settle_rewards(&mut rewards, &self.user)
+    }
+
+    /// Checks the refreshed position can take on `amount` plus its fee, pays
+    /// it out and books the debt. Interest and rewards must already be accrued.
+    fn draw(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        clock: &Clock,
+    ) -> Result<()> {
+        if self.user.is_stale(clock.slot) {
+            return Err(ErrorCode::UserStale.into());
         }
 
-        let borrowable_amount = total_collateral
-            .checked_mul(self.bank.liquidation_threshold)
-            .unwrap();
+        let price = self.bank.price(&self.price_update, clock)?;
+        let fee = self.bank.borrow_fee_for(amount)?;
+        let debt = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+        let borrow_value = self.bank.market_value(debt, &price)?;
 
-        if borrowable_amount < amount {
+        let borrowed_value = self
+            .user
+            .borrowed_value
+            .checked_add(borrow_value)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        if borrowed_value > self.user.allowed_borrow_value {
             return Err(ErrorCode::OverBorrowableAmount.into());
         }
 
+        check_isolation(
+            &mut self.user,
+            &mut self.bank,
+            &mut self.collateral_bank,
+            debt,
+        )?;
+
         let cpi_accounts = TransferChecked {
             from: self.treasury.to_account_info(),
             mint: self.mint.to_account_info(),
@@ -104,8 +137,8 @@ impl<'info> Borrow<'info> {
         };
 
         let seeds = &[
-            &SEED_TREASURY_ACCOUNT[..],
-            &self.bank.mint.as_ref(),
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
             &[self.bank.treasury_bump],
         ];
         let signer_seeds = &[&seeds[..]];
@@ -114,48 +147,260 @@ impl<'info> Borrow<'info> {
             self.token_program.to_account_info(),
             cpi_accounts,
             signer_seeds,
-        );
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
 
-        transfer_checked(cpi_ctx, amount, self.mint.decimals)?;
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
 
-        if self.bank.total_borrowed == 0 {
-            self.bank.total_borrowed = amount;
-            self.bank.total_borrowed_shares = amount;
+        book_borrow_fee(
+            &mut self.user,
+            &mut self.bank,
+            self.referrer.as_deref_mut(),
+            fee,
+        )?;
+        self.bank.credit_borrow(&mut self.user, debt)?;
+
+        self.user.borrowed_value = borrowed_value;
+        self.user.last_updated_borrow = clock.unix_timestamp;
+
+        Ok(())
+    }
+}
+
+/// Splits a borrow fee between the user's referrer, which gets the
+/// `host_fee_bps` share, and the protocol. The first referrer passed is
+/// attributed to the user; from then on its account on `bank` must be passed
+/// whenever a fee is charged. Host fees stay in the treasury until claimed.
+pub fn book_borrow_fee(
+    user: &mut User,
+    bank: &mut Bank,
+    referrer: Option<&mut Referrer>,
+    fee: u64,
+) -> Result<()> {
+    let host_fee = match referrer {
+        Some(referrer) => {
+            user.attribute_referrer(&referrer.owner)?;
+
+            let host_fee = apply_bps(fee, bank.host_fee_bps)?;
+            referrer.claimable = referrer
+                .claimable
+                .checked_add(host_fee)
+                .ok_or(ErrorCode::MathOverflow)?;
+            referrer.total_earned = referrer
+                .total_earned
+                .checked_add(host_fee)
+                .ok_or(ErrorCode::MathOverflow)?;
+            bank.referrer_fees = bank
+                .referrer_fees
+                .checked_add(host_fee)
+                .ok_or(ErrorCode::MathOverflow)?;
+
+            host_fee
+        }
+        None if fee != 0 && user.referrer != Pubkey::default() => {
+            return Err(ErrorCode::ReferrerRequired.into());
+        }
+        None => 0,
+    };
+
+    bank.protocol_fees = bank
+        .protocol_fees
+        .checked_add(fee - host_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    Ok(())
+}
+
+/// Enforces the risk tiers of both banks for a borrow of `amount` from `bank`
+/// and books it against any isolated collateral's debt ceiling.
+pub fn check_isolation(
+    user: &mut User,
+    bank: &mut Bank,
+    collateral_bank: &mut Bank,
+    amount: u64,
+) -> Result<()> {
+    let has_other_debt = user.borrowed_shares(&collateral_bank.mint) != 0;
+    if has_other_debt
+        && (bank.risk_tier == RiskTier::IsolatedBorrow
+            || collateral_bank.risk_tier == RiskTier::IsolatedBorrow)
+    {
+        return Err(ErrorCode::IsolatedBorrowConflict.into());
+    }
+
+    let isolated_debt = bank.isolated_debt_for(amount)?;
+    let borrowable_in_isolation = bank.borrowable_in_isolation;
+
+    for collateral in [bank, collateral_bank] {
+        if collateral.risk_tier != RiskTier::Isolated
+            || user.deposited_shares(&collateral.mint) == 0
+        {
+            continue;
         }
 
-        let borrowed_ratio = amount.checked_div(self.bank.total_borrowed).unwrap();
-        let user_shares = self
-            .bank
-            .total_borrowed_shares
-            .checked_mul(borrowed_ratio)
-            .unwrap();
-
-        match self.mint.to_account_info().key() {
-            key if key == self.user.mint_usdc => {
-                self.user.borrowed_usdc += amount;
-                self.user.borrowed_usdc_shares += user_shares;
-            }
-            _ => {
-                self.user.borrowed_sol += amount;
-                self.user.borrowed_sol_shares += user_shares;
-            }
+        if !borrowable_in_isolation {
+            return Err(ErrorCode::NotBorrowableInIsolation.into());
+        }
+        if user.isolated_debt != 0 && user.isolated_collateral != collateral.mint {
+            return Err(ErrorCode::IsolatedCollateralConflict.into());
         }
+        collateral.add_isolated_debt(isolated_debt)?;
 
-        self.user.last_updated_borrow = Clock::get()?.unix_timestamp;
+        user.isolated_collateral = collateral.mint;
+        user.isolated_debt = user
+            .isolated_debt
+            .checked_add(isolated_debt)
+            .ok_or(ErrorCode::MathOverflow)?;
+    }
 
-        Ok(())
+    Ok(())
+}
+
+/// Returns the ceiling `check_isolation` booked for `user` as `isolated_debt`
+/// of it is repaid or written off. The booked bank must be among `banks`
+/// whenever the user has isolated debt outstanding.
+pub fn release_isolation(
+    user: &mut User,
+    banks: [Option<&mut Bank>; 2],
+    isolated_debt: u64,
+) -> Result<()> {
+    if user.isolated_debt == 0 {
+        return Ok(());
     }
 
-    pub fn calculate_accrued_interest(
+    let bank = banks
+        .into_iter()
+        .flatten()
+        .find(|bank| bank.mint == user.isolated_collateral)
+        .ok_or(ErrorCode::IsolatedBankRequired)?;
+
+    let released = isolated_debt.min(user.isolated_debt);
+    bank.release_isolated_debt(released);
+    user.isolated_debt -= released;
+    if user.isolated_debt == 0 {
+        user.isolated_collateral = Pubkey::default();
+    }
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct BorrowSol<'info> {
+    borrow: Borrow<'info>,
+    /// Temporary account the lamports are unwrapped through, closed before the
+    /// instruction ends.
+    #[account(
+        init,
+        payer = borrow.signer,
+        seeds = [SEED_WSOL_ACCOUNT, borrow.signer.key().as_ref()],
+        bump,
+        token::mint = borrow.mint,
+        token::authority = borrow.signer,
+        token::token_program = token_program
+    )]
+    wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = borrow.token_program.key())]
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> BorrowSol<'info> {
+    /// Borrows `amount` from the SOL bank and unwraps it to the signer.
+    pub fn borrow_sol(
         &mut self,
-        deposited: u64,
-        interest_rate: u64,
-        last_updated: i64,
-    ) -> Result<u64> {
-        let current_time = Clock::get()?.unix_timestamp;
-        let time_diff = current_time - last_updated;
-        let new_value = (deposited as f64 * E.powf(interest_rate as f64 * time_diff as f64)) as u64;
-
-        Ok(new_value)
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let borrow = &mut self.borrow;
+
+        // Unwrapping moves wSOL out of the owner's account, which only the owner can sign.
+        if borrow.signer.key() != borrow.user.owner {
+            return Err(ErrorCode::NotDelegated.into());
+        }
+
+        borrow.borrow(amount, remaining_accounts)?;
+
+        unwrap_sol(
+            &borrow.mint.to_account_info(),
+            borrow.signer.to_account_info(),
+            self.wsol_account.to_account_info(),
+            borrow.user_ata.to_account_info(),
+            borrow.token_program.to_account_info(),
+            amount,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct BorrowFixed<'info> {
+    borrow: Borrow<'info>,
+    #[account(
+        init_if_needed,
+        payer = borrow.signer,
+        seeds = [
+            SEED_FIXED_BORROW,
+            borrow.user.key().as_ref(),
+            borrow.mint.key().as_ref(),
+        ],
+        bump,
+        space = ANCHOR_DISCRIMINATOR + FixedBorrow::INIT_SPACE
+    )]
+    fixed_borrow: Account<'info, FixedBorrow>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> BorrowFixed<'info> {
+    /// Borrows `amount` at the bank's current rate plus its premium for
+    /// `term`, locked until maturity. Fails if that quote is above `max_rate`
+    /// or the user still has an unmatured fixed borrow on the bank.
+    pub fn borrow_fixed(
+        &mut self,
+        amount: u64,
+        term: i64,
+        max_rate: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        bumps: &BorrowFixedBumps,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let borrow = &mut self.borrow;
+        borrow.bank.accrue_interest(&clock)?;
+        let (mut rewards, remaining_accounts) = accrue_rewards(
+            &borrow.bank,
+            &borrow.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        self.fixed_borrow
+            .settle(&mut borrow.bank, &mut borrow.user, clock.unix_timestamp)?;
+        if self.fixed_borrow.is_active() {
+            return Err(ErrorCode::FixedBorrowActive.into());
+        }
+
+        let rate = borrow.bank.fixed_rate_for(term)?;
+        if rate > max_rate {
+            return Err(ErrorCode::FixedRateExceeded.into());
+        }
+
+        let shares_before = borrow.user.borrowed_shares(&borrow.mint.key());
+        borrow.draw(amount, remaining_accounts, &clock)?;
+        let shares = borrow.user.borrowed_shares(&borrow.mint.key()) - shares_before;
+
+        self.fixed_borrow.set_inner(FixedBorrow {
+            user: borrow.user.key(),
+            mint: borrow.mint.key(),
+            shares,
+            amount: borrow.bank.borrow_amount_for(shares)?,
+            rate,
+            maturity: clock
+                .unix_timestamp
+                .checked_add(term)
+                .ok_or(ErrorCode::MathOverflow)?,
+            last_settled: clock.unix_timestamp,
+            bump: bumps.fixed_borrow,
+        });
+
+        settle_rewards(&mut rewards, &borrow.user)
     }
 }
@@ -0,0 +1,92 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    error::ErrorCode, transfer_checked_with_hook, Bank, Referrer, SEED_BANK_ACCOUNT, SEED_REFERRER,
+    SEED_TREASURY_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct ClaimReferrerFees<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program
+    )]
+    treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_REFERRER, signer.key().as_ref(), mint.key().as_ref()],
+        bump = referrer.bump,
+    )]
+    referrer: Account<'info, Referrer>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    signer_ata: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimReferrerFees<'info> {
+    /// Pays the referrer's claimable host fees out of the treasury, as far as
+    /// its idle liquidity allows.
+    pub fn claim_referrer_fees(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let amount = self.referrer.claimable.min(self.treasury.amount);
+        if amount == 0 {
+            return Err(ErrorCode::NoReferrerFees.into());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: self.treasury.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.signer_ata.to_account_info(),
+            authority: self.treasury.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
+            &[self.bank.treasury_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
+
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        self.referrer.claimable -= amount;
+        self.bank.referrer_fees -= amount;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    error::ErrorCode, transfer_checked_with_allowed_hooks, Bank, RewardPool, User, UserReward,
+    SEED_BANK_ACCOUNT, SEED_REWARD_POOL, SEED_REWARD_VAULT, SEED_USER_ACCOUNT, SEED_USER_REWARD,
+};
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    #[account(
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_REWARD_POOL,
+            mint.key().as_ref(),
+            reward_mint.key().as_ref(),
+            &[reward_pool.side as u8],
+        ],
+        bump = reward_pool.bump,
+    )]
+    reward_pool: Account<'info, RewardPool>,
+    #[account(
+        mut,
+        seeds = [SEED_REWARD_VAULT, reward_pool.key().as_ref()],
+        bump = reward_pool.vault_bump,
+        token::mint = reward_mint,
+        token::authority = reward_vault,
+        token::token_program = token_program
+    )]
+    reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_USER_REWARD, reward_pool.key().as_ref(), user.key().as_ref()],
+        bump = user_reward.bump,
+    )]
+    user_reward: Account<'info, UserReward>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = reward_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    signer_ata: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimRewards<'info> {
+    /// Settles the user's rewards from `reward_pool` and pays them out.
+    pub fn claim_rewards(&mut self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let clock = Clock::get()?;
+        let total_shares = self.reward_pool.total_shares(&self.bank);
+        self.reward_pool
+            .accrue(total_shares, clock.unix_timestamp)?;
+
+        let shares = self.reward_pool.user_shares(&self.user);
+        self.user_reward.accrue(&self.reward_pool, shares)?;
+        self.user_reward.shares = shares;
+
+        let amount = self.user_reward.accrued;
+        if amount == 0 {
+            return Err(ErrorCode::NoRewards.into());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: self.reward_vault.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.signer_ata.to_account_info(),
+            authority: self.reward_vault.to_account_info(),
+        };
+
+        let pool = self.reward_pool.key();
+        let seeds = &[
+            SEED_REWARD_VAULT,
+            pool.as_ref(),
+            &[self.reward_pool.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
+
+        transfer_checked_with_allowed_hooks(
+            cpi_ctx,
+            &self.reward_pool.transfer_hook_programs,
+            amount,
+            self.reward_mint.decimals,
+        )?;
+
+        self.user_reward.accrued = 0;
+
+        Ok(())
+    }
+}
+
+pub type RewardPositions<'info> = Vec<(
+    Account<'info, RewardPool>,
+    Option<Account<'info, UserReward>>,
+)>;
+
+/// Takes a `(reward_pool, user_reward)` pair for each of `bank`'s registered
+/// pools, in order, off the front of `remaining_accounts` and accrues them to
+/// `now`. A user without a `UserReward` in a pool passes its empty address and
+/// only the pool accrues. Must run before the position's shares change;
+/// `settle_rewards` finishes the update afterwards. Also returns the accounts
+/// after the pairs, for transfer hooks or the next bank's pools.
+pub fn accrue_rewards<'info>(
+    bank: &Bank,
+    user: &Account<'info, User>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    now: i64,
+) -> Result<(RewardPositions<'info>, &'info [AccountInfo<'info>])> {
+    let count = bank.reward_pools().count();
+    if remaining_accounts.len() < count * 2 {
+        return Err(ErrorCode::InvalidRewardAccounts.into());
+    }
+    let (accounts, remaining_accounts) = remaining_accounts.split_at(count * 2);
+
+    let mut rewards = Vec::with_capacity(count);
+
+    for (pool_address, accounts) in bank.reward_pools().zip(accounts.chunks(2)) {
+        if accounts[0].key() != *pool_address {
+            return Err(ErrorCode::InvalidRewardAccounts.into());
+        }
+
+        let mut pool = Account::<RewardPool>::try_from(&accounts[0])?;
+        let total_shares = pool.total_shares(bank);
+        pool.accrue(total_shares, now)?;
+
+        let user_reward = if accounts[1].data_is_empty() {
+            let (address, _) = Pubkey::find_program_address(
+                &[SEED_USER_REWARD, pool.key().as_ref(), user.key().as_ref()],
+                &crate::ID,
+            );
+            if accounts[1].key() != address {
+                return Err(ErrorCode::InvalidRewardAccounts.into());
+            }
+
+            None
+        } else {
+            let mut user_reward = Account::<UserReward>::try_from(&accounts[1])?;
+            if user_reward.pool != pool.key() || user_reward.user != user.key() {
+                return Err(ErrorCode::InvalidRewardAccounts.into());
+            }
+
+            let shares = pool.user_shares(user);
+            user_reward.accrue(&pool, shares)?;
+
+            Some(user_reward)
+        };
+
+        rewards.push((pool, user_reward));
+    }
+
+    Ok((rewards, remaining_accounts))
+}
+
+/// Accrues each of `bank`'s registered reward pools, passed in order without
+/// user rewards, to `now` ahead of a change to the bank's totals that no
+/// position shares in. Returns them for writing back and the accounts after.
+pub fn accrue_reward_pools<'info>(
+    bank: &Bank,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    now: i64,
+) -> Result<(Vec<Account<'info, RewardPool>>, &'info [AccountInfo<'info>])> {
+    let count = bank.reward_pools().count();
+    if remaining_accounts.len() < count {
+        return Err(ErrorCode::InvalidRewardAccounts.into());
+    }
+    let (accounts, remaining_accounts) = remaining_accounts.split_at(count);
+
+    let mut pools = Vec::with_capacity(count);
+
+    for (pool_address, account) in bank.reward_pools().zip(accounts) {
+        if account.key() != *pool_address {
+            return Err(ErrorCode::InvalidRewardAccounts.into());
+        }
+
+        let mut pool = Account::<RewardPool>::try_from(account)?;
+        let total_shares = pool.total_shares(bank);
+        pool.accrue(total_shares, now)?;
+
+        pools.push(pool);
+    }
+
+    Ok((pools, remaining_accounts))
+}
+
+/// Records `user`'s new shares on each reward position and writes them back.
+pub fn settle_rewards(rewards: &mut RewardPositions, user: &User) -> Result<()> {
+    for (pool, user_reward) in rewards.iter_mut() {
+        pool.exit(&crate::ID)?;
+
+        if let Some(user_reward) = user_reward {
+            user_reward.shares = pool.user_shares(user);
+            user_reward.exit(&crate::ID)?;
+        }
+    }
+
+    Ok(())
+}
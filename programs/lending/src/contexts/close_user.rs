@@ -0,0 +1,102 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    close_program_account, error::ErrorCode, Bank, FixedBorrow, User, UserClosed, UserReward,
+    SEED_BANK_ACCOUNT, SEED_FIXED_BORROW, SEED_USER_ACCOUNT, SEED_USER_REWARD,
+};
+
+#[derive(Accounts)]
+pub struct CloseUser<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+        close = signer,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        seeds = [SEED_BANK_ACCOUNT, user.mint_usdc.as_ref()],
+        bump = usdc_bank.bump,
+    )]
+    usdc_bank: Account<'info, Bank>,
+    #[account(
+        seeds = [SEED_BANK_ACCOUNT, user.mint_sol.as_ref()],
+        bump = sol_bank.bump,
+    )]
+    sol_bank: Account<'info, Bank>,
+    /// CHECK: the user's FixedBorrow address for `mint_usdc`, empty if no
+    /// fixed borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), user.mint_usdc.as_ref()],
+        bump,
+    )]
+    usdc_fixed_borrow: UncheckedAccount<'info>,
+    /// CHECK: the user's FixedBorrow address for `mint_sol`, empty if no
+    /// fixed borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), user.mint_sol.as_ref()],
+        bump,
+    )]
+    sol_fixed_borrow: UncheckedAccount<'info>,
+}
+
+impl<'info> CloseUser<'info> {
+    /// Closes an empty position along with its FixedBorrow accounts and the
+    /// UserReward of every pool registered on the USDC bank, then the SOL
+    /// bank, passed in that order as `remaining_accounts`. A pool the user
+    /// never joined takes its empty UserReward address.
+    pub fn close_user(&mut self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        if !self.user.is_empty() {
+            return Err(ErrorCode::UserNotEmpty.into());
+        }
+
+        // With no borrow shares left, any fixed borrow is spent.
+        for fixed_borrow in [&self.usdc_fixed_borrow, &self.sol_fixed_borrow] {
+            if FixedBorrow::load(fixed_borrow)?.is_some() {
+                close_program_account(fixed_borrow, &self.signer)?;
+            }
+        }
+
+        let pools: Vec<&Pubkey> = self
+            .usdc_bank
+            .reward_pools()
+            .chain(self.sol_bank.reward_pools())
+            .collect();
+        if remaining_accounts.len() != pools.len() {
+            return Err(ErrorCode::InvalidRewardAccounts.into());
+        }
+
+        for (pool, account) in pools.into_iter().zip(remaining_accounts) {
+            let (address, _) = Pubkey::find_program_address(
+                &[SEED_USER_REWARD, pool.as_ref(), self.user.key().as_ref()],
+                &crate::ID,
+            );
+            if account.key() != address {
+                return Err(ErrorCode::InvalidRewardAccounts.into());
+            }
+            if account.data_is_empty() {
+                continue;
+            }
+
+            if Account::<UserReward>::try_from(account)?.accrued != 0 {
+                return Err(ErrorCode::UnclaimedRewards.into());
+            }
+            close_program_account(account, &self.signer)?;
+        }
+
+        emit!(UserClosed {
+            user: self.user.key(),
+            owner: self.signer.key(),
+        });
+
+        Ok(())
+    }
+}
@@ -1,10 +1,15 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{mint_to, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked},
 };
 
-use crate::{Bank, User, SEED_BANK_ACCOUNT, SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT};
+use crate::{
+    accrue_rewards, error::ErrorCode, settle_rewards, transfer_checked_with_hook, wrap_sol, Bank,
+    Referrer, User, PERMISSION_DEPOSIT, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT,
+    SEED_COLLATERAL_VAULT, SEED_REFERRER, SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT,
+    SEED_WSOL_ACCOUNT,
+};
 
 #[derive(Accounts)]
 pub struct Deposit<'info> {
@@ -28,17 +33,53 @@ pub struct Deposit<'info> {
     treasury: InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
-        seeds = [SEED_USER_ACCOUNT, signer.key().as_ref()],
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump = bank.collateral_mint_bump,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, mint.key().as_ref()],
+        bump = bank.collateral_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_vault,
+        token::token_program = token_program
+    )]
+    collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
         bump = user.bump,
     )]
     user: Account<'info, User>,
     #[account(
-        mut,
+        seeds = [SEED_REFERRER, referrer.owner.as_ref(), mint.key().as_ref()],
+        bump = referrer.bump,
+    )]
+    referrer: Option<Account<'info, Referrer>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
         associated_token::mint = mint,
         associated_token::authority = signer,
         associated_token::token_program = token_program
     )]
     user_ata: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: only used as the receipt ATA authority, pinned to `user.owner`.
+    #[account(address = user.owner)]
+    owner: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = collateral_mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     token_program: Interface<'info, TokenInterface>,
     associated_token_program: Program<'info, AssociatedToken>,
@@ -46,7 +87,49 @@ pub struct Deposit<'info> {
 }
 
 impl<'info> Deposit<'info> {
-    pub fn deposit(&mut self, amount: u64) -> Result<()> {
+    /// Deposits the signer's tokens and mints the receipts to the owner's
+    /// receipt account. They only count as collateral once the owner locks
+    /// them with `deposit_receipt_as_collateral`, or straight away if
+    /// `as_collateral` is set.
+    pub fn deposit(
+        &mut self,
+        amount: u64,
+        as_collateral: bool,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            self.user.can_act(&self.signer.key(), PERMISSION_DEPOSIT),
+            ErrorCode::NotDelegated
+        );
+
+        // Deposits carry no fee; this only attributes the user so that the
+        // referrer is paid on their later borrows.
+        if let Some(referrer) = &self.referrer {
+            self.user.attribute_referrer(&referrer.owner)?;
+        }
+
+        self.credit(amount, as_collateral, remaining_accounts)
+    }
+
+    /// Moves the signer's tokens into the bank and mints receipts for them,
+    /// into the collateral vault as `user`'s collateral if `as_collateral`,
+    /// else to the owner's receipt account. Callers decide whether the signer
+    /// may fund the position.
+    fn credit(
+        &mut self,
+        amount: u64,
+        as_collateral: bool,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, remaining_accounts) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
         let cpi_accounts = TransferChecked {
             from: self.user_ata.to_account_info(),
             mint: self.mint.to_account_info(),
@@ -54,42 +137,132 @@ impl<'info> Deposit<'info> {
             authority: self.signer.to_account_info(),
         };
 
-        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
 
-        transfer_checked(cpi_ctx, amount, self.mint.decimals)?;
+        let balance_before = self.treasury.amount;
 
-        if self.bank.total_deposit == 0 {
-            self.bank.total_deposit = amount;
-            self.bank.total_deposit_shares = amount;
-        }
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
 
-        let deposit_ratio = amount.checked_div(self.bank.total_deposit).unwrap();
-        let user_shares = self
-            .bank
-            .total_deposit_shares
-            .checked_mul(deposit_ratio)
-            .unwrap();
-
-        match self.mint.to_account_info().key() {
-            key if key == self.user.mint_usdc => {
-                self.user.deposited_usdc += amount;
-                self.user.deposited_usdc_shares += user_shares;
-            }
-            _ => {
-                self.user.deposited_sol += amount;
-                self.user.deposited_sol_shares += user_shares;
-            }
-        }
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.treasury.reload()?;
+        let amount = self
+            .treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let (shares, to) = if as_collateral {
+            (
+                self.bank.credit_deposit(&mut self.user, amount)?,
+                self.collateral_vault.to_account_info(),
+            )
+        } else {
+            (
+                self.bank.credit_receipts(amount)?,
+                self.receipt_ata.to_account_info(),
+            )
+        };
+
+        let cpi_accounts = MintTo {
+            mint: self.collateral_mint.to_account_info(),
+            to,
+            authority: self.bank.to_account_info(),
+        };
 
-        self.bank.total_deposit.checked_add(amount).unwrap();
-        self.bank
-            .total_deposit_shares
-            .checked_add(user_shares)
-            .unwrap();
+        let seeds = &[
+            SEED_BANK_ACCOUNT,
+            self.bank.mint.as_ref(),
+            &[self.bank.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
 
-        self.user.last_updated = Clock::get()?.unix_timestamp;
-        self.bank.last_updated = Clock::get()?.unix_timestamp;
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        mint_to(cpi_ctx, shares)?;
+
+        self.user.last_updated = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards, &self.user)?;
 
         Ok(())
     }
 }
+
+#[derive(Accounts)]
+pub struct DepositFor<'info> {
+    deposit: Deposit<'info>,
+}
+
+impl<'info> DepositFor<'info> {
+    /// Deposits the signer's tokens straight into someone else's collateral,
+    /// unless the owner has turned third-party deposits off.
+    pub fn deposit_for(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let deposit = &mut self.deposit;
+
+        require!(
+            deposit.user.accepts_third_party_deposits
+                || deposit
+                    .user
+                    .can_act(&deposit.signer.key(), PERMISSION_DEPOSIT),
+            ErrorCode::ThirdPartyDepositsDisabled
+        );
+
+        deposit.credit(amount, true, remaining_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    deposit: Deposit<'info>,
+    /// Temporary account the lamports are wrapped through, closed before the
+    /// instruction ends.
+    #[account(
+        init,
+        payer = deposit.signer,
+        seeds = [SEED_WSOL_ACCOUNT, deposit.signer.key().as_ref()],
+        bump,
+        token::mint = deposit.mint,
+        token::authority = deposit.signer,
+        token::token_program = token_program
+    )]
+    wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = deposit.token_program.key())]
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> DepositSol<'info> {
+    /// Wraps `amount` lamports into the signer's wSOL account and deposits
+    /// them, locking the receipts as collateral if `as_collateral` is set.
+    pub fn deposit_sol(
+        &mut self,
+        amount: u64,
+        as_collateral: bool,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let deposit = &mut self.deposit;
+
+        wrap_sol(
+            &deposit.mint.to_account_info(),
+            deposit.signer.to_account_info(),
+            self.wsol_account.to_account_info(),
+            deposit.user_ata.to_account_info(),
+            deposit.system_program.to_account_info(),
+            deposit.token_program.to_account_info(),
+            amount,
+        )?;
+        deposit.user_ata.reload()?;
+
+        deposit.deposit(amount, as_collateral, remaining_accounts)
+    }
+}
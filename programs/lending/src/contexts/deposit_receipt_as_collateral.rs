@@ -0,0 +1,94 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    accrue_rewards, settle_rewards, Bank, User, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT,
+    SEED_COLLATERAL_VAULT, SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct DepositReceiptAsCollateral<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump = bank.collateral_mint_bump,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, mint.key().as_ref()],
+        bump = bank.collateral_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_vault,
+        token::token_program = token_program
+    )]
+    collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        mut,
+        associated_token::mint = collateral_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    user_collateral_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> DepositReceiptAsCollateral<'info> {
+    /// Locks `shares` receipt tokens from the signer's wallet into the bank's
+    /// collateral vault and credits them to the user's position.
+    pub fn deposit_receipt_as_collateral(
+        &mut self,
+        shares: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, _) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        let cpi_accounts = TransferChecked {
+            from: self.user_collateral_ata.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            to: self.collateral_vault.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+
+        transfer_checked(cpi_ctx, shares, self.collateral_mint.decimals)?;
+
+        let amount = self.bank.deposit_amount_for(shares)?;
+        self.bank.lock_receipts(&mut self.user, shares, amount)?;
+
+        self.user.last_updated = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards, &self.user)
+    }
+}
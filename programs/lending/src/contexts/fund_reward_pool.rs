@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::{
+    error::ErrorCode, transfer_checked_with_allowed_hooks, Bank, RewardPool, SEED_BANK_ACCOUNT,
+    SEED_REWARD_POOL, SEED_REWARD_VAULT,
+};
+
+#[derive(Accounts)]
+pub struct FundRewardPool<'info> {
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+        constraint = bank.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    bank: Account<'info, Bank>,
+    reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_REWARD_POOL,
+            mint.key().as_ref(),
+            reward_mint.key().as_ref(),
+            &[reward_pool.side as u8],
+        ],
+        bump = reward_pool.bump,
+    )]
+    reward_pool: Account<'info, RewardPool>,
+    #[account(
+        mut,
+        seeds = [SEED_REWARD_VAULT, reward_pool.key().as_ref()],
+        bump = reward_pool.vault_bump,
+        token::mint = reward_mint,
+        token::authority = reward_vault,
+        token::token_program = token_program
+    )]
+    reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = signer,
+        token::token_program = token_program
+    )]
+    signer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FundRewardPool<'info> {
+    /// Adds `amount` to the pool's undistributed rewards and sets the rate
+    /// they are emitted at. Emissions up to now use the previous rate.
+    pub fn fund_reward_pool(
+        &mut self,
+        amount: u64,
+        emission_per_second: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let total_shares = self.reward_pool.total_shares(&self.bank);
+        self.reward_pool
+            .accrue(total_shares, clock.unix_timestamp)?;
+
+        let cpi_accounts = TransferChecked {
+            from: self.signer_token_account.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.reward_vault.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+
+        let balance_before = self.reward_vault.amount;
+
+        transfer_checked_with_allowed_hooks(
+            cpi_ctx,
+            &self.reward_pool.transfer_hook_programs,
+            amount,
+            self.reward_mint.decimals,
+        )?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.reward_vault.reload()?;
+        let amount = self
+            .reward_vault
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        self.reward_pool.undistributed = self
+            .reward_pool
+            .undistributed
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.reward_pool.emission_per_second = emission_per_second;
+
+        Ok(())
+    }
+}
@@ -1,7 +1,20 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use anchor_spl::{
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            transfer_hook::TransferHook, BaseStateWithExtensions, ExtensionType,
+            StateWithExtensions,
+        },
+    },
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
 
-use crate::{Bank, ANCHOR_DISCRIMINATOR, SEED_BANK_ACCOUNT, SEED_TREASURY_ACCOUNT};
+use crate::{
+    error::ErrorCode, Bank, RiskTier, ANCHOR_DISCRIMINATOR, MAX_REWARD_POOLS,
+    MAX_TRANSFER_HOOK_PROGRAMS, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT, SEED_COLLATERAL_VAULT,
+    SEED_FEE_VAULT, SEED_INSURANCE_VAULT, SEED_TREASURY_ACCOUNT,
+};
 
 #[derive(Accounts)]
 pub struct InitBank<'info> {
@@ -26,34 +39,152 @@ pub struct InitBank<'info> {
         token::token_program = token_program
     )]
     treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump,
+        mint::decimals = mint.decimals,
+        mint::authority = bank,
+        mint::token_program = token_program
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_COLLATERAL_VAULT, mint.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_vault,
+        token::token_program = token_program
+    )]
+    collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_FEE_VAULT, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = fee_vault,
+        token::token_program = token_program
+    )]
+    fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_INSURANCE_VAULT, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = insurance_vault,
+        token::token_program = token_program
+    )]
+    insurance_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     token_program: Interface<'info, TokenInterface>,
     system_program: Program<'info, System>,
 }
 
 impl<'info> InitBank<'info> {
+    /// Lists the mint. A mint with a transfer hook is only accepted when its
+    /// hook program is among `allowed_hook_programs`, which seeds the bank's
+    /// allowlist.
     pub fn init_bank(
         &mut self,
         liquidation_threshold: u64,
         max_ltv: u64,
+        allowed_hook_programs: Vec<Pubkey>,
         bumps: &InitBankBumps,
     ) -> Result<()> {
+        if allowed_hook_programs.len() > MAX_TRANSFER_HOOK_PROGRAMS {
+            return Err(ErrorCode::TooManyTransferHookPrograms.into());
+        }
+        self.validate_mint_extensions(&allowed_hook_programs)?;
+
+        let mut transfer_hook_programs = [Pubkey::default(); MAX_TRANSFER_HOOK_PROGRAMS];
+        transfer_hook_programs[..allowed_hook_programs.len()]
+            .copy_from_slice(&allowed_hook_programs);
+
+        let clock = Clock::get()?;
+
         self.bank.set_inner(Bank {
             authority: self.signer.key(),
             mint: self.mint.key(),
+            mint_decimals: self.mint.decimals,
+            collateral_mint: self.collateral_mint.key(),
+            oracle_feed_id: [0; 32],
             total_deposit: 0,
             total_borrowed: 0,
             total_deposit_shares: 0,
+            collateral_shares: 0,
             total_borrowed_shares: 0,
             liquidation_threshold,
             liquidation_bonus: 1000,
+            max_liquidation_bonus: 1000,
+            liquidation_bonus_ramp: 1000,
             liquidation_close_factor: 5000,
+            liquidation_fee: 0,
             max_ltv,
-            last_updated: 0,
+            last_updated: clock.unix_timestamp,
+            last_updated_slot: clock.slot,
             interest_rate: 500,
+            fixed_rate_premium: 0,
+            max_fixed_term: 0,
+            risk_tier: RiskTier::Standard,
+            debt_ceiling: 0,
+            isolated_debt: 0,
+            borrowable_in_isolation: false,
+            emode_category: 0,
+            transfer_hook_programs,
+            reward_pools: [Pubkey::default(); MAX_REWARD_POOLS],
+            insurance_fee: 0,
+            insurance_reserve: 0,
+            insurance_withdrawal_amount: 0,
+            insurance_withdrawal_unlocks_at: 0,
+            borrow_fee_bps: 0,
+            host_fee_bps: 0,
+            protocol_fees: 0,
+            referrer_fees: 0,
+            swap_program: Pubkey::default(),
             treasury_bump: bumps.treasury,
+            collateral_mint_bump: bumps.collateral_mint,
+            collateral_vault_bump: bumps.collateral_vault,
+            fee_vault_bump: bumps.fee_vault,
+            insurance_vault_bump: bumps.insurance_vault,
             bump: bumps.bank,
         });
         Ok(())
     }
+
+    /// Rejects unsupported extensions and transfer hooks outside
+    /// `allowed_hook_programs`.
+    fn validate_mint_extensions(&self, allowed_hook_programs: &[Pubkey]) -> Result<()> {
+        let mint_info = self.mint.to_account_info();
+        if *mint_info.owner != spl_token_2022::ID {
+            return Ok(());
+        }
+
+        let data = mint_info.try_borrow_data()?;
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+        for extension in mint.get_extension_types()? {
+            match extension {
+                ExtensionType::PermanentDelegate
+                | ExtensionType::NonTransferable
+                | ExtensionType::ConfidentialTransferMint
+                | ExtensionType::ConfidentialTransferFeeConfig => {
+                    return Err(ErrorCode::UnsupportedMintExtension.into());
+                }
+                ExtensionType::TransferHook => {
+                    let transfer_hook = mint.get_extension::<TransferHook>()?;
+                    if let Some(program_id) = Option::<Pubkey>::from(transfer_hook.program_id) {
+                        if !allowed_hook_programs.contains(&program_id) {
+                            return Err(ErrorCode::TransferHookNotAllowed.into());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
 }
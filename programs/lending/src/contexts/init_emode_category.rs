@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::ErrorCode, EmodeCategory, Market, ANCHOR_DISCRIMINATOR, BPS_DENOMINATOR,
+    SEED_EMODE_CATEGORY, SEED_MARKET,
+};
+
+#[derive(Accounts)]
+#[instruction(id: u8)]
+pub struct InitEmodeCategory<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    #[account(
+        seeds = [SEED_MARKET],
+        bump = market.bump,
+        constraint = market.admin == signer.key() @ ErrorCode::NotMarketAdmin,
+    )]
+    market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_EMODE_CATEGORY, &[id]],
+        bump,
+        space = ANCHOR_DISCRIMINATOR + EmodeCategory::INIT_SPACE
+    )]
+    emode_category: Account<'info, EmodeCategory>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitEmodeCategory<'info> {
+    /// Creates category `id`. Banks are only placed in it by the market admin
+    /// through `set_bank_emode`.
+    pub fn init_emode_category(
+        &mut self,
+        id: u8,
+        max_ltv: u64,
+        liquidation_threshold: u64,
+        liquidation_bonus: u64,
+        bumps: &InitEmodeCategoryBumps,
+    ) -> Result<()> {
+        if id == 0 {
+            return Err(ErrorCode::InvalidEmodeCategory.into());
+        }
+        if max_ltv > liquidation_threshold
+            || liquidation_threshold > BPS_DENOMINATOR
+            || liquidation_bonus > BPS_DENOMINATOR
+        {
+            return Err(ErrorCode::InvalidEmodeParameters.into());
+        }
+
+        self.emode_category.set_inner(EmodeCategory {
+            id,
+            max_ltv,
+            liquidation_threshold,
+            liquidation_bonus,
+            bump: bumps.emode_category,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+
+use crate::{Market, ANCHOR_DISCRIMINATOR, SEED_MARKET};
+
+#[derive(Accounts)]
+pub struct InitMarket<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_MARKET],
+        bump,
+        space = ANCHOR_DISCRIMINATOR + Market::INIT_SPACE
+    )]
+    market: Account<'info, Market>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitMarket<'info> {
+    /// Creates the market with the signer as admin. Deployment must call this
+    /// before any bank can be given an oracle.
+    pub fn init_market(&mut self, bumps: &InitMarketBumps) -> Result<()> {
+        self.market.set_inner(Market {
+            admin: self.signer.key(),
+            bump: bumps.market,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{Bank, Referrer, ANCHOR_DISCRIMINATOR, SEED_BANK_ACCOUNT, SEED_REFERRER};
+
+#[derive(Accounts)]
+pub struct InitReferrer<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    /// CHECK: the referrer being registered. Only it can claim the fees, so
+    /// anyone may pay to open its account, e.g. before a referred borrow.
+    owner: UncheckedAccount<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_REFERRER, owner.key().as_ref(), mint.key().as_ref()],
+        bump,
+        space = ANCHOR_DISCRIMINATOR + Referrer::INIT_SPACE
+    )]
+    referrer: Account<'info, Referrer>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitReferrer<'info> {
+    pub fn init_referrer(&mut self, bumps: &InitReferrerBumps) -> Result<()> {
+        self.referrer.set_inner(Referrer {
+            owner: self.owner.key(),
+            mint: self.mint.key(),
+            claimable: 0,
+            total_earned: 0,
+            bump: bumps.referrer,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{
+    error::ErrorCode, Bank, RewardPool, RewardSide, ANCHOR_DISCRIMINATOR,
+    MAX_TRANSFER_HOOK_PROGRAMS, SEED_BANK_ACCOUNT, SEED_REWARD_POOL, SEED_REWARD_VAULT,
+};
+
+#[derive(Accounts)]
+#[instruction(side: RewardSide)]
+pub struct InitRewardPool<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+        constraint = bank.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    bank: Account<'info, Bank>,
+    reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+            SEED_REWARD_POOL,
+            mint.key().as_ref(),
+            reward_mint.key().as_ref(),
+            &[side as u8],
+        ],
+        bump,
+        space = ANCHOR_DISCRIMINATOR + RewardPool::INIT_SPACE
+    )]
+    reward_pool: Account<'info, RewardPool>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_REWARD_VAULT, reward_pool.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = reward_vault,
+        token::token_program = token_program
+    )]
+    reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitRewardPool<'info> {
+    /// Creates an unfunded pool emitting `reward_mint` to the `side` of the
+    /// bank and registers it there. Up to `MAX_REWARD_POOLS` may run on a
+    /// bank at once. `allowed_hook_programs` is the reward mint's own transfer
+    /// hook allowlist.
+    pub fn init_reward_pool(
+        &mut self,
+        side: RewardSide,
+        allowed_hook_programs: Vec<Pubkey>,
+        bumps: &InitRewardPoolBumps,
+    ) -> Result<()> {
+        if allowed_hook_programs.len() > MAX_TRANSFER_HOOK_PROGRAMS {
+            return Err(ErrorCode::TooManyTransferHookPrograms.into());
+        }
+        let mut transfer_hook_programs = [Pubkey::default(); MAX_TRANSFER_HOOK_PROGRAMS];
+        transfer_hook_programs[..allowed_hook_programs.len()]
+            .copy_from_slice(&allowed_hook_programs);
+
+        let slot = self
+            .bank
+            .reward_pools
+            .iter_mut()
+            .find(|pool| **pool == Pubkey::default())
+            .ok_or(ErrorCode::TooManyRewardPools)?;
+        *slot = self.reward_pool.key();
+
+        let clock = Clock::get()?;
+
+        self.reward_pool.set_inner(RewardPool {
+            mint: self.mint.key(),
+            reward_mint: self.reward_mint.key(),
+            side,
+            emission_per_second: 0,
+            reward_index: 0,
+            undistributed: 0,
+            last_updated: clock.unix_timestamp,
+            transfer_hook_programs,
+            vault_bump: bumps.reward_vault,
+            bump: bumps.reward_pool,
+        });
+
+        Ok(())
+    }
+}
@@ -1,15 +1,20 @@
 use anchor_lang::prelude::*;
 
-use crate::{User, ANCHOR_DISCRIMINATOR, SEED_USER_ACCOUNT};
+use crate::{error::ErrorCode, User, ANCHOR_DISCRIMINATOR, SEED_USER_ACCOUNT};
 
 #[derive(Accounts)]
+#[instruction(mint_usdc: Pubkey, mint_sol: Pubkey, sub_account: u16)]
 pub struct InitUser<'info> {
     #[account(mut)]
     signer: Signer<'info>,
     #[account(
         init,
-        payer = user,
-        seeds = [SEED_USER_ACCOUNT, signer.key().as_ref()],
+        payer = signer,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &sub_account.to_le_bytes(),
+        ],
         bump,
         space = ANCHOR_DISCRIMINATOR + User::INIT_SPACE
     )]
@@ -19,9 +24,18 @@ pub struct InitUser<'info> {
 }
 
 impl<'info> InitUser<'info> {
-    pub fn init_user(&mut self, mint_usdc: Pubkey, bumps: &InitUserBumps) -> Result<()> {
+    pub fn init_user(
+        &mut self,
+        mint_usdc: Pubkey,
+        mint_sol: Pubkey,
+        sub_account: u16,
+        bumps: &InitUserBumps,
+    ) -> Result<()> {
+        require_keys_neq!(mint_usdc, mint_sol, ErrorCode::UnsupportedMint);
+
         self.user.set_inner(User {
             owner: self.signer.key(),
+            sub_account,
             deposited_sol: 0,
             deposited_sol_shares: 0,
             borrowed_sol: 0,
@@ -31,8 +45,22 @@ impl<'info> InitUser<'info> {
             borrowed_usdc: 0,
             borrowed_usdc_shares: 0,
             mint_usdc,
+            mint_sol,
             last_updated: 0,
             last_updated_borrow: 0,
+            deposited_value: 0,
+            borrowed_value: 0,
+            allowed_borrow_value: 0,
+            unhealthy_borrow_value: 0,
+            last_refreshed_slot: 0,
+            emode_category: 0,
+            delegate: Pubkey::default(),
+            delegate_permissions: 0,
+            accepts_third_party_deposits: true,
+            accepts_third_party_repays: true,
+            isolated_collateral: Pubkey::default(),
+            isolated_debt: 0,
+            referrer: Pubkey::default(),
             bump: bumps.user,
         });
 
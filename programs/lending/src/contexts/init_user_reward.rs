@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    Bank, RewardPool, User, UserReward, ANCHOR_DISCRIMINATOR, SEED_BANK_ACCOUNT, SEED_USER_ACCOUNT,
+    SEED_USER_REWARD,
+};
+
+#[derive(Accounts)]
+pub struct InitUserReward<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    #[account(
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        seeds = [SEED_BANK_ACCOUNT, reward_pool.mint.as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(mut)]
+    reward_pool: Account<'info, RewardPool>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_USER_REWARD, reward_pool.key().as_ref(), user.key().as_ref()],
+        bump,
+        space = ANCHOR_DISCRIMINATOR + UserReward::INIT_SPACE
+    )]
+    user_reward: Account<'info, UserReward>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitUserReward<'info> {
+    /// Starts the user earning from `reward_pool` on the shares it holds now.
+    pub fn init_user_reward(&mut self, bumps: &InitUserRewardBumps) -> Result<()> {
+        let clock = Clock::get()?;
+        let total_shares = self.reward_pool.total_shares(&self.bank);
+        self.reward_pool
+            .accrue(total_shares, clock.unix_timestamp)?;
+
+        self.user_reward.set_inner(UserReward {
+            user: self.user.key(),
+            pool: self.reward_pool.key(),
+            reward_index: self.reward_pool.reward_index,
+            shares: self.reward_pool.user_shares(&self.user),
+            accrued: 0,
+            bump: bumps.user_reward,
+        });
+
+        Ok(())
+    }
+}
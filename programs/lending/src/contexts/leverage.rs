@@ -0,0 +1,336 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{mint_to, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked},
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    accrue_rewards, book_borrow_fee, check_isolation, error::ErrorCode, invoke_swap,
+    refresh_with_emode, settle_rewards, take_hook_accounts, transfer_checked_with_hook, Bank,
+    EmodeCategory, FixedBorrow, Referrer, User, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT,
+    SEED_COLLATERAL_VAULT, SEED_EMODE_CATEGORY, SEED_FIXED_BORROW, SEED_REFERRER,
+    SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct Leverage<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    /// Owner-only: the borrowed tokens and the swap pass through the signer's
+    /// own token accounts, so delegates cannot lever a position up.
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Box<Account<'info, User>>,
+    /// CHECK: the user's FixedBorrow address for `mint`, empty if no fixed
+    /// borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), mint.key().as_ref()],
+        bump,
+    )]
+    fixed_borrow: UncheckedAccount<'info>,
+    /// CHECK: the user's FixedBorrow address for `deposit_mint`, empty if no
+    /// fixed borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), deposit_mint.key().as_ref()],
+        bump,
+    )]
+    collateral_fixed_borrow: UncheckedAccount<'info>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[user.emode_category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+
+    mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Box<Account<'info, Bank>>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program
+    )]
+    treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    user_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    price_update: Box<Account<'info, PriceUpdateV2>>,
+
+    deposit_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, deposit_mint.key().as_ref()],
+        bump = collateral_bank.bump,
+        constraint = collateral_bank.mint != mint.key() @ ErrorCode::InvalidCollateralBank,
+        constraint = user.is_pair(&collateral_bank.mint, &mint.key()) @ ErrorCode::InvalidCollateralBank,
+    )]
+    collateral_bank: Box<Account<'info, Bank>>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, deposit_mint.key().as_ref()],
+        bump = collateral_bank.treasury_bump,
+        token::mint = deposit_mint,
+        token::authority = collateral_treasury,
+        token::token_program = token_program
+    )]
+    collateral_treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_MINT, deposit_mint.key().as_ref()],
+        bump = collateral_bank.collateral_mint_bump,
+    )]
+    receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, deposit_mint.key().as_ref()],
+        bump = collateral_bank.collateral_vault_bump,
+        token::mint = receipt_mint,
+        token::authority = receipt_vault,
+        token::token_program = token_program
+    )]
+    receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = deposit_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    collateral_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    collateral_price_update: Box<Account<'info, PriceUpdateV2>>,
+    #[account(
+        mut,
+        seeds = [SEED_REFERRER, referrer.owner.as_ref(), mint.key().as_ref()],
+        bump = referrer.bump,
+    )]
+    referrer: Option<Box<Account<'info, Referrer>>>,
+
+    /// CHECK: only invoked, and only when it is the bank's configured swap program.
+    #[account(
+        executable,
+        constraint = bank.swap_program != Pubkey::default()
+            && swap_program.key() == bank.swap_program @ ErrorCode::SwapProgramNotAllowed,
+    )]
+    swap_program: UncheckedAccount<'info>,
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> Leverage<'info> {
+    /// Flash-borrows `amount` of `mint` into the signer's account, swaps it
+    /// through `swap_program` using `swap_data` and `remaining_accounts` as
+    /// the swap instruction, deposits the proceeds into `collateral_bank` and
+    /// records the borrow. The position only has to be healthy at the end.
+    /// Reward pairs for `bank`, then `collateral_bank`, lead the remaining
+    /// accounts, followed by `borrow_hook_accounts` transfer-hook accounts for
+    /// `mint`, `deposit_hook_accounts` for `deposit_mint` and the swap accounts.
+    pub fn leverage(
+        &mut self,
+        amount: u64,
+        min_collateral_out: u64,
+        swap_data: Vec<u8>,
+        borrow_hook_accounts: u8,
+        deposit_hook_accounts: u8,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        self.collateral_bank.accrue_interest(&clock)?;
+        let (mut borrow_rewards, remaining_accounts) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        let (mut collateral_rewards, remaining_accounts) = accrue_rewards(
+            &self.collateral_bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.fixed_borrow,
+            &mut self.bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.collateral_fixed_borrow,
+            &mut self.collateral_bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+        let (borrow_hook_accounts, remaining_accounts) =
+            take_hook_accounts(remaining_accounts, borrow_hook_accounts)?;
+        let (deposit_hook_accounts, remaining_accounts) =
+            take_hook_accounts(remaining_accounts, deposit_hook_accounts)?;
+
+        let cpi_accounts = TransferChecked {
+            from: self.treasury.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.user_ata.to_account_info(),
+            authority: self.treasury.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
+            &[self.bank.treasury_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(borrow_hook_accounts.to_vec());
+
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        let balance_before = self.collateral_ata.amount;
+
+        invoke_swap(&self.swap_program, remaining_accounts, swap_data)?;
+
+        self.collateral_ata.reload()?;
+        let received = self
+            .collateral_ata
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::SlippageExceeded)?;
+
+        if received < min_collateral_out {
+            return Err(ErrorCode::SlippageExceeded.into());
+        }
+
+        self.deposit_collateral(received, deposit_hook_accounts)?;
+        let debt = self.record_borrow(amount)?;
+
+        check_isolation(
+            &mut self.user,
+            &mut self.bank,
+            &mut self.collateral_bank,
+            debt,
+        )?;
+
+        let price = self.bank.price(&self.price_update, &clock)?;
+        let collateral_price = self
+            .collateral_bank
+            .price(&self.collateral_price_update, &clock)?;
+
+        let positions = [
+            (Bank::clone(&self.bank), price),
+            (Bank::clone(&self.collateral_bank), collateral_price),
+        ];
+        refresh_with_emode(&mut self.user, &positions, &self.emode_category, clock.slot)?;
+
+        if self.user.borrowed_value > self.user.allowed_borrow_value {
+            return Err(ErrorCode::Undercollateralized.into());
+        }
+
+        self.user.last_updated = clock.unix_timestamp;
+        self.user.last_updated_borrow = clock.unix_timestamp;
+
+        settle_rewards(&mut borrow_rewards, &self.user)?;
+        settle_rewards(&mut collateral_rewards, &self.user)
+    }
+
+    fn deposit_collateral(
+        &mut self,
+        amount: u64,
+        hook_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            from: self.collateral_ata.to_account_info(),
+            mint: self.deposit_mint.to_account_info(),
+            to: self.collateral_treasury.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(hook_accounts.to_vec());
+
+        let balance_before = self.collateral_treasury.amount;
+
+        transfer_checked_with_hook(
+            cpi_ctx,
+            &self.collateral_bank,
+            amount,
+            self.deposit_mint.decimals,
+        )?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.collateral_treasury.reload()?;
+        let amount = self
+            .collateral_treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let user_shares = self
+            .collateral_bank
+            .credit_deposit(&mut self.user, amount)?;
+
+        let cpi_accounts = MintTo {
+            mint: self.receipt_mint.to_account_info(),
+            to: self.receipt_vault.to_account_info(),
+            authority: self.collateral_bank.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_BANK_ACCOUNT,
+            self.collateral_bank.mint.as_ref(),
+            &[self.collateral_bank.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        mint_to(cpi_ctx, user_shares)?;
+
+        Ok(())
+    }
+
+    /// Books `amount` plus the borrow fee as the user's debt and returns the
+    /// total.
+    fn record_borrow(&mut self, amount: u64) -> Result<u64> {
+        let fee = self.bank.borrow_fee_for(amount)?;
+        book_borrow_fee(
+            &mut self.user,
+            &mut self.bank,
+            self.referrer.as_deref_mut().map(|referrer| &mut **referrer),
+            fee,
+        )?;
+
+        let amount = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+        self.bank.credit_borrow(&mut self.user, amount)?;
+
+        Ok(amount)
+    }
+}
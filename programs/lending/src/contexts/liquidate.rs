@@ -1,52 +1,124 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked, transfer_checked},
+    token_interface::{burn, Burn, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use pyth_solana_receiver_sdk::price_update::{Price, PriceUpdateV2};
 
 use crate::error::ErrorCode;
 use crate::{
-    Bank, User, MAX_AGE, SEED_BANK_ACCOUNT, SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT,
-    SOL_USD_FEED_ID, USDC_USD_FEED_ID,
+    accrue_rewards, apply_bps, refresh_with_emode, release_isolation, settle_rewards,
+    transfer_checked_with_hook, Bank, EmodeCategory, FixedBorrow, User, BPS_DENOMINATOR,
+    LIQUIDATION_DUST_VALUE, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT, SEED_COLLATERAL_VAULT,
+    SEED_EMODE_CATEGORY, SEED_FEE_VAULT, SEED_FIXED_BORROW, SEED_INSURANCE_VAULT,
+    SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT,
 };
 
 #[derive(Accounts)]
 pub struct Liquidate<'info> {
     #[account(mut)]
     liquidator: Signer<'info>,
-    collateral_mint: InterfaceAccount<'info, Mint>,
-    borrowed_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = collateral_mint.key() != borrowed_mint.key() @ ErrorCode::InvalidLiquidationMints,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    borrowed_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         mut,
         seeds = [SEED_BANK_ACCOUNT, collateral_mint.key().as_ref()],
         bump = collateral_bank.bump,
     )]
-    collateral_bank: Account<'info, Bank>,
+    collateral_bank: Box<Account<'info, Bank>>,
     #[account(
         mut,
-        seeds = [SEED_BANK_ACCOUNT, borrowed_bank.key().as_ref()],
+        seeds = [SEED_BANK_ACCOUNT, borrowed_mint.key().as_ref()],
         bump = borrowed_bank.bump
     )]
-    borrowed_bank: Account<'info, Bank>,
+    borrowed_bank: Box<Account<'info, Bank>>,
     #[account(
         mut,
         seeds = [SEED_TREASURY_ACCOUNT, collateral_mint.key().as_ref()],
-        bump = collateral_bank.treasury_bump
+        bump = collateral_bank.treasury_bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_treasury,
+        token::token_program = token_program
     )]
-    collateral_treasury: InterfaceAccount<'info, TokenAccount>,
+    collateral_treasury: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         seeds = [SEED_TREASURY_ACCOUNT, borrowed_mint.key().as_ref()],
-        bump = borrowed_bank.treasury_bump
+        bump = borrowed_bank.treasury_bump,
+        token::mint = borrowed_mint,
+        token::authority = borrowed_treasury,
+        token::token_program = token_program
+    )]
+    borrowed_treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_MINT, collateral_mint.key().as_ref()],
+        bump = collateral_bank.collateral_mint_bump,
+    )]
+    collateral_receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, collateral_mint.key().as_ref()],
+        bump = collateral_bank.collateral_vault_bump,
+        token::mint = collateral_receipt_mint,
+        token::authority = collateral_receipt_vault,
+        token::token_program = token_program
+    )]
+    collateral_receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SEED_FEE_VAULT, collateral_mint.key().as_ref()],
+        bump = collateral_bank.fee_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = fee_vault,
+        token::token_program = token_program
+    )]
+    fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SEED_INSURANCE_VAULT, collateral_mint.key().as_ref()],
+        bump = collateral_bank.insurance_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = insurance_vault,
+        token::token_program = token_program
     )]
-    borrowed_treasury: InterfaceAccount<'info, TokenAccount>,
+    insurance_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        seeds = [SEED_USER_ACCOUNT, liquidator.key().as_ref()],
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
         bump = user.bump
     )]
-    user: Account<'info, User>,
+    user: Box<Account<'info, User>>,
+    /// CHECK: the user's FixedBorrow address for `borrowed_mint`, empty if no
+    /// fixed borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), borrowed_mint.key().as_ref()],
+        bump,
+    )]
+    fixed_borrow: UncheckedAccount<'info>,
+    /// CHECK: the user's FixedBorrow address for `collateral_mint`, empty if
+    /// no fixed borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), collateral_mint.key().as_ref()],
+        bump,
+    )]
+    collateral_fixed_borrow: UncheckedAccount<'info>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[user.emode_category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+    collateral_price_update: Box<Account<'info, PriceUpdateV2>>,
+    borrowed_price_update: Box<Account<'info, PriceUpdateV2>>,
     #[account(
         init_if_needed,
         payer = liquidator,
@@ -54,7 +126,7 @@ pub struct Liquidate<'info> {
         associated_token::authority = liquidator,
         associated_token::token_program = token_program
     )]
-    liquidator_collateral_ata: InterfaceAccount<'info, TokenAccount>,
+    liquidator_collateral_ata: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init_if_needed,
         payer = liquidator,
@@ -62,8 +134,7 @@ pub struct Liquidate<'info> {
         associated_token::authority = liquidator,
         associated_token::token_program = token_program
     )]
-    liquidator_borrowed_ata: InterfaceAccount<'info, TokenAccount>,
-    price_update: Account<'info, PriceUpdateV2>,
+    liquidator_borrowed_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     token_program: Interface<'info, TokenInterface>,
     associated_token_program: Program<'info, AssociatedToken>,
@@ -71,61 +142,275 @@ pub struct Liquidate<'info> {
 }
 
 impl<'info> Liquidate<'info> {
-    pub fn liquidate(&mut self) -> Result<()> {
-        let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)?;
-        let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)?;
-
-        let sol_price =
-            self.price_update
-                .get_price_no_older_than(&Clock::get()?, MAX_AGE, &sol_feed_id)?;
-        let usdc_price =
-            self.price_update
-                .get_price_no_older_than(&Clock::get()?, MAX_AGE, &usdc_feed_id)?;
-
-        let total_collateral = (sol_price.price as u64 * self.user.deposited_sol)
-            + (usdc_price.price as u64 * self.user.deposited_usdc);
-        let total_borrowed = (sol_price.price as u64 * self.user.borrowed_sol)
-            + (usdc_price.price as u64 * self.user.borrowed_usdc);
-
-        let health_factor =
-            (total_collateral * self.collateral_bank.liquidation_threshold) / total_borrowed;
-
-        if health_factor >= 1 {
+    /// Repays up to `amount` of an unhealthy user's `borrowed_mint` debt
+    /// (`u64::MAX` for as much as the close factor allows) and seizes
+    /// collateral worth the repaid value plus the liquidation bonus. The
+    /// protocol's `liquidation_fee` share of the bonus is split between the
+    /// insurance vault, by `insurance_fee`, and the fee vault. Reward pairs
+    /// for the borrowed bank, then the collateral bank, lead `remaining_accounts`.
+    pub fn liquidate(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.collateral_bank.accrue_interest(&clock)?;
+        self.borrowed_bank.accrue_interest(&clock)?;
+        let (mut borrow_rewards, remaining_accounts) = accrue_rewards(
+            &self.borrowed_bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        let (mut collateral_rewards, remaining_accounts) = accrue_rewards(
+            &self.collateral_bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.fixed_borrow,
+            &mut self.borrowed_bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.collateral_fixed_borrow,
+            &mut self.collateral_bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+
+        let collateral_price = self
+            .collateral_bank
+            .price(&self.collateral_price_update, &clock)?;
+        let borrowed_price = self
+            .borrowed_bank
+            .price(&self.borrowed_price_update, &clock)?;
+        self.refresh(collateral_price, borrowed_price, clock.slot)?;
+
+        if !self.user.is_liquidatable() {
             return Err(ErrorCode::NotUndercollateralized.into());
         }
 
-        let liquidation_amount = total_borrowed * self.collateral_bank.liquidation_close_factor;
+        let borrowed_shares = self.user.borrowed_shares(&self.borrowed_mint.key());
+        let debt = self.borrowed_bank.borrow_amount_for(borrowed_shares)?;
+        let deposited_shares = self.user.deposited_shares(&self.collateral_mint.key());
+        let collateral = self.collateral_bank.deposit_amount_for(deposited_shares)?;
+
+        let mut repay_amount = amount.min(self.max_repay(debt, &borrowed_price)?);
+        let repay_value = self
+            .borrowed_bank
+            .market_value(repay_amount, &borrowed_price)?;
+        let bonus = self.liquidation_bonus()?;
+        let seize_value = apply_bps(repay_value, BPS_DENOMINATOR + bonus)?;
+        let mut seize_amount = self
+            .collateral_bank
+            .amount_for_value(seize_value, &collateral_price)?;
+
+        // Not enough collateral left to cover the bonus: take all of it and
+        // scale the repayment down to match.
+        if seize_amount > collateral {
+            repay_amount = mul_div(repay_amount, collateral, seize_amount)?;
+            seize_amount = collateral;
+        }
+
+        if repay_amount == 0 || seize_amount == 0 {
+            return Err(ErrorCode::LiquidationTooSmall.into());
+        }
+
+        let repaid = self.repay_debt(repay_amount, remaining_accounts)?;
+
+        // Seize in proportion to what actually arrived, for transfer-fee mints.
+        if repaid < repay_amount {
+            seize_amount = mul_div(seize_amount, repaid, repay_amount)?;
+        }
 
-        let transfer_to_bank = TransferChecked {
+        let bonus_amount =
+            seize_amount - mul_div(seize_amount, BPS_DENOMINATOR, BPS_DENOMINATOR + bonus)?;
+        let fee = apply_bps(bonus_amount, self.collateral_bank.liquidation_fee)?;
+
+        self.seize_collateral(seize_amount, fee, remaining_accounts)?;
+
+        self.refresh(collateral_price, borrowed_price, clock.slot)?;
+
+        settle_rewards(&mut borrow_rewards, &self.user)?;
+        settle_rewards(&mut collateral_rewards, &self.user)
+    }
+
+    /// The close factor caps each liquidation, unless what it would leave
+    /// behind is dust nobody would bother liquidating.
+    fn max_repay(&self, debt: u64, borrowed_price: &Price) -> Result<u64> {
+        let max_repay = apply_bps(debt, self.borrowed_bank.liquidation_close_factor)?;
+        let remaining_value = self
+            .borrowed_bank
+            .market_value(debt - max_repay, borrowed_price)?;
+
+        if remaining_value < LIQUIDATION_DUST_VALUE {
+            Ok(debt)
+        } else {
+            Ok(max_repay)
+        }
+    }
+
+    /// Moves `amount` of the liquidator's tokens into the borrowed treasury and
+    /// books it against the user's debt. Returns how much arrived.
+    fn repay_debt(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<u64> {
+        let cpi_accounts = TransferChecked {
             from: self.liquidator_borrowed_ata.to_account_info(),
             mint: self.borrowed_mint.to_account_info(),
-            to: self.borrowed_bank.to_account_info(),
-            authority: self.liquidator.to_account_info()
+            to: self.borrowed_treasury.to_account_info(),
+            authority: self.liquidator.to_account_info(),
         };
 
-        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), transfer_to_bank);
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
 
-        transfer_checked(cpi_ctx, liquidation_amount, self.borrowed_mint.decimals)?;
+        let balance_before = self.borrowed_treasury.amount;
 
-        // transfer liquidation value and bonus to liquidator
-        let liquidation_bonus = (liquidation_amount * self.collateral_bank.liquidation_bonus) + liquidation_amount;
-    
-        let transfer_to_liquidator = TransferChecked {
-            from: self.collateral_bank.to_account_info(),
-            mint: self.collateral_mint.to_account_info(),
-            to: self.liquidator_collateral_ata.to_account_info(),
-            authority: self.collateral_bank.to_account_info()
-        };
+        transfer_checked_with_hook(
+            cpi_ctx,
+            &self.borrowed_bank,
+            amount,
+            self.borrowed_mint.decimals,
+        )?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.borrowed_treasury.reload()?;
+        let amount = self
+            .borrowed_treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        self.borrowed_bank.debit_borrow(&mut self.user, amount)?;
+
+        let isolated_debt = self.borrowed_bank.isolated_debt_for(amount)?;
+        release_isolation(
+            &mut self.user,
+            [
+                Some(&mut **self.borrowed_bank),
+                Some(&mut **self.collateral_bank),
+            ],
+            isolated_debt,
+        )?;
 
+        Ok(amount)
+    }
+
+    /// Pays `amount` of the user's collateral out of the treasury, `fee` of it
+    /// to the protocol vaults and the rest to the liquidator, and burns the matching
+    /// deposit shares.
+    fn seize_collateral(
+        &mut self,
+        amount: u64,
+        fee: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
         let seeds = &[
-            &SEED_TREASURY_ACCOUNT[..],
-            &self.collateral_bank.mint.as_ref(),
+            SEED_TREASURY_ACCOUNT,
+            self.collateral_bank.mint.as_ref(),
             &[self.collateral_bank.treasury_bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
-        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), transfer_to_liquidator, signer_seeds);
+        let insurance = apply_bps(fee, self.collateral_bank.insurance_fee)?;
+
+        for (to, amount) in [
+            (
+                self.liquidator_collateral_ata.to_account_info(),
+                amount - fee,
+            ),
+            (self.fee_vault.to_account_info(), fee - insurance),
+            (self.insurance_vault.to_account_info(), insurance),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+
+            let cpi_accounts = TransferChecked {
+                from: self.collateral_treasury.to_account_info(),
+                mint: self.collateral_mint.to_account_info(),
+                to,
+                authority: self.collateral_treasury.to_account_info(),
+            };
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            )
+            .with_remaining_accounts(remaining_accounts.to_vec());
 
-        transfer_checked(cpi_ctx, liquidation_bonus, self.collateral_mint.decimals)
+            transfer_checked_with_hook(
+                cpi_ctx,
+                &self.collateral_bank,
+                amount,
+                self.collateral_mint.decimals,
+            )?;
+        }
+
+        let shares_to_remove = self.collateral_bank.debit_deposit(&mut self.user, amount)?;
+
+        let cpi_accounts = Burn {
+            mint: self.collateral_receipt_mint.to_account_info(),
+            from: self.collateral_receipt_vault.to_account_info(),
+            authority: self.collateral_receipt_vault.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_COLLATERAL_VAULT,
+            self.collateral_bank.mint.as_ref(),
+            &[self.collateral_bank.collateral_vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        burn(cpi_ctx, shares_to_remove)?;
+
+        Ok(())
     }
+
+    fn refresh(&mut self, collateral_price: Price, borrowed_price: Price, slot: u64) -> Result<()> {
+        let positions = [
+            (Bank::clone(&self.collateral_bank), collateral_price),
+            (Bank::clone(&self.borrowed_bank), borrowed_price),
+        ];
+
+        refresh_with_emode(&mut self.user, &positions, &self.emode_category, slot)
+    }
+
+    /// E-mode pairs are correlated, so they keep their category's flat bonus;
+    /// everything else gets the collateral bank's health-scaled bonus.
+    fn liquidation_bonus(&self) -> Result<u64> {
+        match &self.emode_category {
+            Some(emode)
+                if self.collateral_bank.emode_category == emode.id
+                    && self.borrowed_bank.emode_category == emode.id =>
+            {
+                Ok(emode.liquidation_bonus)
+            }
+            _ => self
+                .collateral_bank
+                .liquidation_bonus_for(self.user.borrowed_value, self.user.unhealthy_borrow_value),
+        }
+    }
+}
+
+fn mul_div(value: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let result = (value as u128)
+        .checked_mul(numerator as u128)
+        .and_then(|value| value.checked_div(denominator as u128))
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    u64::try_from(result).map_err(|_| ErrorCode::MathOverflow.into())
 }
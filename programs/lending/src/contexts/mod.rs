@@ -1,20 +1,103 @@
+pub mod init_market;
+pub use init_market::*;
+
 pub mod init_bank;
 pub use init_bank::*;
 
+pub mod set_bank_oracle;
+pub use set_bank_oracle::*;
+
+pub mod update_bank;
+pub use update_bank::*;
+
+pub mod init_emode_category;
+pub use init_emode_category::*;
+
+pub mod set_bank_emode;
+pub use set_bank_emode::*;
+
 pub mod init_user;
 pub use init_user::*;
 
+pub mod set_delegate;
+pub use set_delegate::*;
+
+pub mod set_third_party_deposits;
+pub use set_third_party_deposits::*;
+pub mod set_third_party_repays;
+pub use set_third_party_repays::*;
+
+pub mod close_user;
+pub use close_user::*;
+
+pub mod refresh_bank;
+pub use refresh_bank::*;
+
+pub mod refresh_user;
+pub use refresh_user::*;
+
+pub mod set_emode;
+pub use set_emode::*;
+
 pub mod deposit;
 pub use deposit::*;
 
 pub mod withdraw;
 pub use withdraw::*;
 
+pub mod deposit_receipt_as_collateral;
+pub use deposit_receipt_as_collateral::*;
+
+pub mod withdraw_receipt_collateral;
+pub use withdraw_receipt_collateral::*;
+
+pub mod redeem_receipt;
+pub use redeem_receipt::*;
+
 pub mod borrow;
 pub use borrow::*;
 
+pub mod leverage;
+pub use leverage::*;
+
+pub mod swap_collateral;
+pub use swap_collateral::*;
+
 pub mod repay;
 pub use repay::*;
 
 pub mod liquidate;
 pub use liquidate::*;
+
+pub mod socialize_loss;
+pub use socialize_loss::*;
+
+pub mod sweep_reserves;
+pub use sweep_reserves::*;
+
+pub mod request_insurance_withdrawal;
+pub use request_insurance_withdrawal::*;
+
+pub mod withdraw_insurance;
+pub use withdraw_insurance::*;
+
+pub mod init_referrer;
+pub use init_referrer::*;
+
+pub mod claim_referrer_fees;
+pub use claim_referrer_fees::*;
+
+pub mod init_reward_pool;
+pub use init_reward_pool::*;
+
+pub mod fund_reward_pool;
+pub use fund_reward_pool::*;
+
+pub mod init_user_reward;
+pub use init_user_reward::*;
+
+pub mod claim_rewards;
+pub use claim_rewards::*;
+
+pub mod settle_fixed_borrow;
+pub use settle_fixed_borrow::*;
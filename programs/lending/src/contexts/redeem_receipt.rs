@@ -0,0 +1,125 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{burn, Burn, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    accrue_reward_pools, error::ErrorCode, transfer_checked_with_hook, Bank, SEED_BANK_ACCOUNT,
+    SEED_COLLATERAL_MINT, SEED_TREASURY_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct RedeemReceipt<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program
+    )]
+    treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump = bank.collateral_mint_bump,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = collateral_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    receipt_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> RedeemReceipt<'info> {
+    /// Burns `shares` receipt tokens from the signer's wallet (`u64::MAX` for
+    /// all of them) and pays out the tokens they are worth. Receipts locked as
+    /// collateral must be unlocked with `withdraw_receipt_collateral` first.
+    /// The bank's reward pools lead the transfer-hook accounts.
+    pub fn redeem_receipt(
+        &mut self,
+        shares: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (pools, remaining_accounts) =
+            accrue_reward_pools(&self.bank, remaining_accounts, clock.unix_timestamp)?;
+
+        let shares = if shares == u64::MAX {
+            self.receipt_ata.amount
+        } else {
+            shares
+        };
+
+        if shares > self.receipt_ata.amount {
+            return Err(ErrorCode::InsufficientFunds.into());
+        }
+
+        let cpi_accounts = Burn {
+            mint: self.collateral_mint.to_account_info(),
+            from: self.receipt_ata.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+
+        burn(cpi_ctx, shares)?;
+
+        let amount = self.bank.redeem_receipts(shares)?;
+
+        let cpi_accounts = TransferChecked {
+            from: self.treasury.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.user_ata.to_account_info(),
+            authority: self.treasury.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
+            &[self.bank.treasury_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
+
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        for pool in pools {
+            pool.exit(&crate::ID)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{Bank, SEED_BANK_ACCOUNT};
+
+#[derive(Accounts)]
+pub struct RefreshBank<'info> {
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+}
+
+impl<'info> RefreshBank<'info> {
+    pub fn refresh_bank(&mut self) -> Result<()> {
+        self.bank.accrue_interest(&Clock::get()?)
+    }
+}
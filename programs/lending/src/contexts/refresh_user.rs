@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::{Price, PriceUpdateV2};
+
+use crate::{
+    error::ErrorCode, Bank, EmodeCategory, FixedBorrow, User, SEED_BANK_ACCOUNT,
+    SEED_EMODE_CATEGORY, SEED_FIXED_BORROW, SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct RefreshUser<'info> {
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[user.emode_category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+}
+
+impl<'info> RefreshUser<'info> {
+    pub fn refresh_user(&mut self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let clock = Clock::get()?;
+
+        refresh_from_accounts(
+            &mut self.user,
+            &self.emode_category,
+            remaining_accounts,
+            &clock,
+        )
+    }
+}
+
+/// Revalues `user` from the positions in `remaining_accounts`. Their fixed
+/// borrows are settled into copies, since the banks are not written back,
+/// and only the resulting valuation is kept.
+pub fn refresh_from_accounts<'info>(
+    user: &mut Account<'info, User>,
+    emode_category: &Option<Account<EmodeCategory>>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    clock: &Clock,
+) -> Result<()> {
+    let mut settled = User::clone(user);
+    let positions = load_positions(&user.key(), &mut settled, remaining_accounts, clock)?;
+    refresh_with_emode(&mut settled, &positions, emode_category, clock.slot)?;
+
+    user.deposited_value = settled.deposited_value;
+    user.borrowed_value = settled.borrowed_value;
+    user.allowed_borrow_value = settled.allowed_borrow_value;
+    user.unhealthy_borrow_value = settled.unhealthy_borrow_value;
+    user.last_refreshed_slot = settled.last_refreshed_slot;
+
+    Ok(())
+}
+
+/// Expects a `(bank, price_update, fixed_borrow)` triple in
+/// `remaining_accounts` for every bank the user has a deposit or borrow in,
+/// where `fixed_borrow` is the user's FixedBorrow address for that bank. Returns
+/// the banks accrued to `clock` alongside their prices, with any fixed borrow
+/// settled into them and into `user`.
+pub fn load_positions<'info>(
+    address: &Pubkey,
+    user: &mut User,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    clock: &Clock,
+) -> Result<Vec<(Bank, Price)>> {
+    if !remaining_accounts.len().is_multiple_of(3) {
+        return Err(ErrorCode::InvalidRefreshAccounts.into());
+    }
+
+    let mut positions = Vec::with_capacity(remaining_accounts.len() / 3);
+
+    for accounts in remaining_accounts.chunks(3) {
+        let bank_account = Account::<Bank>::try_from(&accounts[0])?;
+        let (bank_address, _) = Pubkey::find_program_address(
+            &[SEED_BANK_ACCOUNT, bank_account.mint.as_ref()],
+            &crate::ID,
+        );
+        if bank_address != bank_account.key() {
+            return Err(ErrorCode::InvalidRefreshAccounts.into());
+        }
+        if bank_account.mint != user.mint_usdc && bank_account.mint != user.mint_sol {
+            return Err(ErrorCode::UnsupportedMint.into());
+        }
+
+        let mut bank = bank_account.into_inner();
+        bank.accrue_interest(clock)?;
+
+        let price_update = Account::<PriceUpdateV2>::try_from(&accounts[1])?;
+        let price = bank.price(&price_update, clock)?;
+
+        let (fixed_borrow_address, _) = Pubkey::find_program_address(
+            &[SEED_FIXED_BORROW, address.as_ref(), bank.mint.as_ref()],
+            &crate::ID,
+        );
+        if fixed_borrow_address != accounts[2].key() {
+            return Err(ErrorCode::InvalidRefreshAccounts.into());
+        }
+        if let Some(mut fixed_borrow) = FixedBorrow::load(&accounts[2])? {
+            fixed_borrow.settle(&mut bank, user, clock.unix_timestamp)?;
+        }
+
+        positions.push((bank, price));
+    }
+
+    Ok(positions)
+}
+
+/// Refreshes `user`, insisting on its e-mode category account when it has one
+/// so a caller cannot value the position with the stricter bank parameters.
+pub fn refresh_with_emode(
+    user: &mut User,
+    positions: &[(Bank, Price)],
+    emode_category: &Option<Account<EmodeCategory>>,
+    slot: u64,
+) -> Result<()> {
+    let emode = emode_category.as_deref();
+    if user.emode_category != 0 && emode.is_none() {
+        return Err(ErrorCode::InvalidEmodeCategory.into());
+    }
+
+    user.refresh(positions, emode, slot)
+}
@@ -1,14 +1,16 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
 use crate::error::ErrorCode;
 
-use crate::{Bank, User};
+use crate::{
+    accrue_rewards, release_isolation, settle_rewards, transfer_checked_with_hook, wrap_sol, Bank,
+    FixedBorrow, RewardPositions, User, PERMISSION_REPAY, SEED_BANK_ACCOUNT, SEED_FIXED_BORROW,
+    SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT, SEED_WSOL_ACCOUNT,
+};
 
 #[derive(Accounts)]
 pub struct Repay<'info> {
@@ -17,14 +19,21 @@ pub struct Repay<'info> {
     mint: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
-        seeds = [b"bank".as_ref(), mint.key().as_ref()],
-        bump,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
     )]
     bank: Account<'info, Bank>,
     #[account(
         mut,
-        seeds = [b"treasury".as_ref(), mint.key().as_ref()],
-        bump,
+        seeds = [SEED_BANK_ACCOUNT, collateral_bank.mint.as_ref()],
+        bump = collateral_bank.bump,
+        constraint = collateral_bank.mint != mint.key() @ ErrorCode::InvalidCollateralBank,
+    )]
+    collateral_bank: Option<Account<'info, Bank>>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
         token::mint = mint,
         token::authority = treasury,
         token::token_program = token_program
@@ -32,10 +41,22 @@ pub struct Repay<'info> {
     treasury: InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
-        seeds = [b"user", signer.key().as_ref()],
-        bump,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
     )]
     user: Account<'info, User>,
+    /// CHECK: the user's FixedBorrow address for `mint`, empty if no fixed
+    /// borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), mint.key().as_ref()],
+        bump,
+    )]
+    fixed_borrow: UncheckedAccount<'info>,
     #[account(
         init_if_needed,
         payer = signer,
@@ -51,28 +72,82 @@ pub struct Repay<'info> {
 }
 
 impl<'info> Repay<'info> {
-    pub fn repay(&mut self, amount: u64) -> Result<()> {
-        let borrowed_value: u64;
-
-        match self.mint.to_account_info().key() {
-            key if key == self.user.mint_usdc => {
-                borrowed_value = self.user.borrowed_usdc;
-            }
-            _ => {
-                borrowed_value = self.user.borrowed_sol;
-            }
-        }
+    pub fn repay(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            self.user.can_act(&self.signer.key(), PERMISSION_REPAY),
+            ErrorCode::NotDelegated
+        );
 
-        let time_diff = self.user.last_updated_borrow - Clock::get()?.unix_timestamp;
+        self.pay_down(amount, remaining_accounts)
+    }
 
-        self.bank.total_borrowed -= (self.bank.total_borrowed as f64
-            * E.powf(self.bank.interest_rate as f64 * time_diff as f64))
-            as u64;
+    /// Pays down `user`'s debt with tokens from the signer's account. Callers
+    /// decide whether the signer may act for the position.
+    fn pay_down(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let (mut rewards, remaining_accounts) = self.settle_debt(&clock, remaining_accounts)?;
+        let amount = self.resolve_amount(amount)?;
 
-        let value_per_share =
-            self.bank.total_borrowed as f64 / self.bank.total_borrowed_shares as f64;
+        self.pay(amount, remaining_accounts, &clock)?;
 
-        let user_value = borrowed_value / value_per_share as u64;
+        settle_rewards(&mut rewards, &self.user)
+    }
+
+    /// Accrues the bank and its reward pools, then books any fixed-rate
+    /// interest into the user's shares so they price at what is actually
+    /// owed. Returns the reward positions and the accounts left for hooks.
+    fn settle_debt(
+        &mut self,
+        clock: &Clock,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<(RewardPositions<'info>, &'info [AccountInfo<'info>])> {
+        self.bank.accrue_interest(clock)?;
+        let rewards = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.fixed_borrow,
+            &mut self.bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+
+        Ok(rewards)
+    }
+
+    /// Maps the `u64::MAX` sentinel to the full debt once `settle_debt` has
+    /// run, so a repay can clear a position without racing interest.
+    fn resolve_amount(&self, amount: u64) -> Result<u64> {
+        if amount != u64::MAX {
+            return Ok(amount);
+        }
+
+        self.bank
+            .borrow_amount_for(self.user.borrowed_shares(&self.mint.key()))
+    }
+
+    /// Moves `amount` from the signer's account into the treasury and books
+    /// it against the settled debt.
+    fn pay(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        clock: &Clock,
+    ) -> Result<()> {
+        let user_value = self
+            .bank
+            .borrow_amount_for(self.user.borrowed_shares(&self.mint.key()))?;
 
         if amount > user_value {
             return Err(ErrorCode::OverRepay.into());
@@ -82,34 +157,117 @@ impl<'info> Repay<'info> {
             from: self.user_ata.to_account_info(),
             mint: self.mint.to_account_info(),
             to: self.treasury.to_account_info(),
-            authority: self.user.to_account_info(),
+            authority: self.signer.to_account_info(),
         };
 
-        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
 
-        transfer_checked(cpi_ctx, amount, self.mint.decimals)?;
+        let balance_before = self.treasury.amount;
 
-        let borrowed_ratio = amount.checked_div(self.bank.total_borrowed).unwrap();
-        let user_shares = self
-            .bank
-            .total_borrowed
-            .checked_mul(borrowed_ratio)
-            .unwrap();
-
-        match self.mint.to_account_info().key() {
-            key if key == self.user.mint_usdc => {
-                self.user.borrowed_usdc -= amount;
-                self.user.borrowed_usdc_shares -= user_shares;
-            }
-            _ => {
-                self.user.borrowed_sol -= amount;
-                self.user.borrowed_sol_shares -= user_shares;
-            }
-        }
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.treasury.reload()?;
+        let amount = self
+            .treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-        self.bank.total_borrowed -= amount;
-        self.bank.total_borrowed_shares -= user_shares;
+        self.bank.debit_borrow(&mut self.user, amount)?;
+
+        let isolated_debt = self.bank.isolated_debt_for(amount)?;
+        release_isolation(
+            &mut self.user,
+            [Some(&mut self.bank), self.collateral_bank.as_deref_mut()],
+            isolated_debt,
+        )?;
+
+        self.user.last_updated_borrow = clock.unix_timestamp;
 
         Ok(())
     }
 }
+
+#[derive(Accounts)]
+pub struct RepayObligation<'info> {
+    repay: Repay<'info>,
+}
+
+impl<'info> RepayObligation<'info> {
+    /// Repays someone else's debt from the signer's tokens, unless the owner
+    /// has turned third-party repayments off.
+    pub fn repay_obligation(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let repay = &mut self.repay;
+
+        require!(
+            repay.user.accepts_third_party_repays
+                || repay.user.can_act(&repay.signer.key(), PERMISSION_REPAY),
+            ErrorCode::ThirdPartyRepaysDisabled
+        );
+
+        repay.pay_down(amount, remaining_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct RepaySol<'info> {
+    repay: Repay<'info>,
+    /// Temporary account the lamports are wrapped through, closed before the
+    /// instruction ends.
+    #[account(
+        init,
+        payer = repay.signer,
+        seeds = [SEED_WSOL_ACCOUNT, repay.signer.key().as_ref()],
+        bump,
+        token::mint = repay.mint,
+        token::authority = repay.signer,
+        token::token_program = token_program
+    )]
+    wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = repay.token_program.key())]
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> RepaySol<'info> {
+    /// Wraps what `amount` resolves to in lamports into the signer's wSOL
+    /// account and repays with it.
+    pub fn repay_sol(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let repay = &mut self.repay;
+        require!(
+            repay.user.can_act(&repay.signer.key(), PERMISSION_REPAY),
+            ErrorCode::NotDelegated
+        );
+
+        let clock = Clock::get()?;
+        let (mut rewards, remaining_accounts) = repay.settle_debt(&clock, remaining_accounts)?;
+
+        // Wrap only what is owed rather than the sentinel itself.
+        let amount = repay.resolve_amount(amount)?;
+
+        wrap_sol(
+            &repay.mint.to_account_info(),
+            repay.signer.to_account_info(),
+            self.wsol_account.to_account_info(),
+            repay.user_ata.to_account_info(),
+            repay.system_program.to_account_info(),
+            repay.token_program.to_account_info(),
+            amount,
+        )?;
+        repay.user_ata.reload()?;
+
+        repay.pay(amount, remaining_accounts, &clock)?;
+        settle_rewards(&mut rewards, &repay.user)
+    }
+}
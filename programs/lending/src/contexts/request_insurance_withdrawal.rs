@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{error::ErrorCode, Bank, INSURANCE_WITHDRAWAL_TIMELOCK, SEED_BANK_ACCOUNT};
+
+#[derive(Accounts)]
+pub struct RequestInsuranceWithdrawal<'info> {
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+        constraint = bank.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    bank: Account<'info, Bank>,
+}
+
+impl<'info> RequestInsuranceWithdrawal<'info> {
+    /// Queues a withdrawal of `amount` from the insurance vault that unlocks
+    /// after `INSURANCE_WITHDRAWAL_TIMELOCK`. Replaces any pending request; an
+    /// `amount` of 0 cancels it.
+    pub fn request_insurance_withdrawal(&mut self, amount: u64) -> Result<()> {
+        let clock = Clock::get()?;
+
+        self.bank.insurance_withdrawal_amount = amount;
+        self.bank.insurance_withdrawal_unlocks_at = clock
+            .unix_timestamp
+            .checked_add(INSURANCE_WITHDRAWAL_TIMELOCK)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{
+    error::ErrorCode, Bank, EmodeCategory, Market, SEED_BANK_ACCOUNT, SEED_EMODE_CATEGORY,
+    SEED_MARKET,
+};
+
+#[derive(Accounts)]
+pub struct SetBankEmode<'info> {
+    signer: Signer<'info>,
+    #[account(
+        seeds = [SEED_MARKET],
+        bump = market.bump,
+        constraint = market.admin == signer.key() @ ErrorCode::NotMarketAdmin,
+    )]
+    market: Account<'info, Market>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    /// Required unless the bank is leaving e-mode.
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[emode_category.id]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+}
+
+impl<'info> SetBankEmode<'info> {
+    /// Places the bank in e-mode `category`, or takes it out with 0.
+    pub fn set_bank_emode(&mut self, category: u8) -> Result<()> {
+        if category != 0
+            && self
+                .emode_category
+                .as_ref()
+                .map(|emode_category| emode_category.id)
+                != Some(category)
+        {
+            return Err(ErrorCode::InvalidEmodeCategory.into());
+        }
+
+        self.bank.emode_category = category;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{error::ErrorCode, Bank, Market, SEED_BANK_ACCOUNT, SEED_MARKET};
+
+#[derive(Accounts)]
+pub struct SetBankOracle<'info> {
+    signer: Signer<'info>,
+    #[account(
+        seeds = [SEED_MARKET],
+        bump = market.bump,
+        constraint = market.admin == signer.key() @ ErrorCode::NotMarketAdmin,
+    )]
+    market: Account<'info, Market>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+}
+
+impl<'info> SetBankOracle<'info> {
+    pub fn set_bank_oracle(&mut self, feed_id: [u8; 32]) -> Result<()> {
+        if feed_id == [0; 32] {
+            return Err(ErrorCode::OracleNotSet.into());
+        }
+
+        self.bank.oracle_feed_id = feed_id;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+
+use crate::{User, SEED_USER_ACCOUNT};
+
+#[derive(Accounts)]
+pub struct SetDelegate<'info> {
+    signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+}
+
+impl<'info> SetDelegate<'info> {
+    /// Lets `delegate` act on the position with the given `PERMISSION_*` bits.
+    /// Withdrawals and borrows still pay out to the owner only; pass the
+    /// default pubkey to remove the delegate.
+    pub fn set_delegate(&mut self, delegate: Pubkey, permissions: u8) -> Result<()> {
+        self.user.delegate = delegate;
+        self.user.delegate_permissions = permissions;
+
+        Ok(())
+    }
+}
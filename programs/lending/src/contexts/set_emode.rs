@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::ErrorCode, refresh_from_accounts, EmodeCategory, User, SEED_EMODE_CATEGORY,
+    SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+#[instruction(category: u8)]
+pub struct SetEmode<'info> {
+    signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+}
+
+impl<'info> SetEmode<'info> {
+    /// Switches the user's e-mode category (0 leaves e-mode) and revalues the
+    /// position, which takes the same remaining accounts as `refresh_user`.
+    pub fn set_emode(
+        &mut self,
+        category: u8,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        self.user.emode_category = category;
+
+        let clock = Clock::get()?;
+        refresh_from_accounts(
+            &mut self.user,
+            &self.emode_category,
+            remaining_accounts,
+            &clock,
+        )?;
+
+        if self.user.borrowed_value > self.user.allowed_borrow_value {
+            return Err(ErrorCode::Undercollateralized.into());
+        }
+
+        Ok(())
+    }
+}
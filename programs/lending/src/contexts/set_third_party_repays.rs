@@ -0,0 +1,26 @@
+use anchor_lang::prelude::*;
+
+use crate::{User, SEED_USER_ACCOUNT};
+
+#[derive(Accounts)]
+pub struct SetThirdPartyRepays<'info> {
+    signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+}
+
+impl<'info> SetThirdPartyRepays<'info> {
+    pub fn set_third_party_repays(&mut self, allowed: bool) -> Result<()> {
+        self.user.accepts_third_party_repays = allowed;
+
+        Ok(())
+    }
+}
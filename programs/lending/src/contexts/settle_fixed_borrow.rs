@@ -0,0 +1,57 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{
+    accrue_rewards, settle_rewards, Bank, FixedBorrow, User, SEED_BANK_ACCOUNT, SEED_FIXED_BORROW,
+    SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct SettleFixedBorrow<'info> {
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), mint.key().as_ref()],
+        bump = fixed_borrow.bump,
+    )]
+    fixed_borrow: Account<'info, FixedBorrow>,
+}
+
+impl<'info> SettleFixedBorrow<'info> {
+    /// Permissionless crank that books fixed-rate interest into the user's
+    /// debt and converts the position to variable once it has matured.
+    pub fn settle_fixed_borrow(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, _) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        self.fixed_borrow
+            .settle(&mut self.bank, &mut self.user, clock.unix_timestamp)?;
+
+        settle_rewards(&mut rewards, &self.user)
+    }
+}
@@ -0,0 +1,219 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::{
+    accrue_rewards, error::ErrorCode, release_isolation, settle_rewards,
+    transfer_checked_with_hook, BadDebtSocialized, Bank, FixedBorrow, User, SEED_BANK_ACCOUNT,
+    SEED_FEE_VAULT, SEED_FIXED_BORROW, SEED_INSURANCE_VAULT, SEED_TREASURY_ACCOUNT,
+    SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct SocializeLoss<'info> {
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+        constraint = bank.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    bank: Account<'info, Bank>,
+    /// Required when the user's debt is booked against an isolated bank.
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, collateral_bank.mint.as_ref()],
+        bump = collateral_bank.bump,
+        constraint = collateral_bank.mint != mint.key() @ ErrorCode::InvalidCollateralBank,
+    )]
+    collateral_bank: Option<Account<'info, Bank>>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program
+    )]
+    treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_FEE_VAULT, mint.key().as_ref()],
+        bump = bank.fee_vault_bump,
+        token::mint = mint,
+        token::authority = fee_vault,
+        token::token_program = token_program
+    )]
+    fee_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_INSURANCE_VAULT, mint.key().as_ref()],
+        bump = bank.insurance_vault_bump,
+        token::mint = mint,
+        token::authority = insurance_vault,
+        token::token_program = token_program
+    )]
+    insurance_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    /// CHECK: the user's FixedBorrow address for `mint`, empty if no fixed
+    /// borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), mint.key().as_ref()],
+        bump,
+    )]
+    fixed_borrow: UncheckedAccount<'info>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SocializeLoss<'info> {
+    /// Lets the bank authority write off the `mint` debt of a user with no
+    /// collateral left. Insurance,
+    /// both unswept in the treasury and in the insurance vault, pays first,
+    /// then protocol fees the same way; the rest comes out of every depositor's balance by
+    /// shrinking `total_deposit` against unchanged shares.
+    pub fn socialize_loss(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        if self.user.deposited_sol_shares != 0 || self.user.deposited_usdc_shares != 0 {
+            return Err(ErrorCode::UserNotInsolvent.into());
+        }
+
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, remaining_accounts) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.fixed_borrow,
+            &mut self.bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+
+        let borrowed_shares = self.user.borrowed_shares(&self.mint.key());
+        let debt = self.bank.borrow_amount_for(borrowed_shares)?;
+        if debt == 0 {
+            return Err(ErrorCode::UserNotInsolvent.into());
+        }
+
+        let mint = self.bank.mint;
+
+        // Insurance set aside from interest is already in the treasury.
+        let unswept = debt.min(self.bank.insurance_reserve);
+        self.bank.insurance_reserve -= unswept;
+
+        let seeds = &[
+            SEED_INSURANCE_VAULT,
+            mint.as_ref(),
+            &[self.bank.insurance_vault_bump],
+        ];
+        let insurance_vault = self.insurance_vault.to_account_info();
+        let vaulted = self.cover_from(
+            insurance_vault,
+            self.insurance_vault.amount,
+            debt - unswept,
+            seeds,
+            remaining_accounts,
+        )?;
+        let covered_by_insurance = unswept + vaulted;
+
+        let unswept = (debt - covered_by_insurance).min(self.bank.protocol_fees);
+        self.bank.protocol_fees -= unswept;
+
+        let seeds = &[SEED_FEE_VAULT, mint.as_ref(), &[self.bank.fee_vault_bump]];
+        let fee_vault = self.fee_vault.to_account_info();
+        let vaulted = self.cover_from(
+            fee_vault,
+            self.fee_vault.amount,
+            debt - covered_by_insurance - unswept,
+            seeds,
+            remaining_accounts,
+        )?;
+        let covered_by_reserves = unswept + vaulted;
+
+        let socialized = debt - covered_by_insurance - covered_by_reserves;
+
+        self.bank.debit_borrow(&mut self.user, debt)?;
+        self.bank.total_deposit = self.bank.total_deposit.saturating_sub(socialized);
+
+        let isolated_debt = self.bank.isolated_debt_for(debt)?;
+        release_isolation(
+            &mut self.user,
+            [Some(&mut self.bank), self.collateral_bank.as_deref_mut()],
+            isolated_debt,
+        )?;
+
+        self.user.last_updated_borrow = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards, &self.user)?;
+
+        emit!(BadDebtSocialized {
+            user: self.user.key(),
+            bank: self.bank.key(),
+            debt,
+            covered_by_insurance,
+            covered_by_reserves,
+            socialized,
+        });
+
+        Ok(())
+    }
+
+    /// Moves up to `debt` from a self-owned vault holding `balance` into the
+    /// treasury and returns how much arrived.
+    fn cover_from(
+        &mut self,
+        vault: AccountInfo<'info>,
+        balance: u64,
+        debt: u64,
+        seeds: &[&[u8]],
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<u64> {
+        let amount = debt.min(balance);
+        if amount == 0 {
+            return Ok(0);
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: vault.clone(),
+            mint: self.mint.to_account_info(),
+            to: self.treasury.to_account_info(),
+            authority: vault,
+        };
+
+        let signer_seeds = &[seeds];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
+
+        let balance_before = self.treasury.amount;
+
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.treasury.reload()?;
+        self.treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow.into())
+    }
+}
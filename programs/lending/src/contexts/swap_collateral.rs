@@ -0,0 +1,502 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        burn, mint_to, Burn, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    accrue_rewards, error::ErrorCode, invoke_swap, refresh_with_emode, release_isolation,
+    settle_rewards, take_hook_accounts, transfer_checked_with_hook, Bank, EmodeCategory,
+    FixedBorrow, RewardPositions, RiskTier, User, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT,
+    SEED_COLLATERAL_VAULT, SEED_EMODE_CATEGORY, SEED_FIXED_BORROW, SEED_TREASURY_ACCOUNT,
+    SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct SwapCollateral<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    /// Owner-only: the withdrawn collateral and the swap pass through the
+    /// signer's own token accounts, so delegates cannot swap a position's
+    /// collateral or repay with it.
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Box<Account<'info, User>>,
+    /// CHECK: the user's FixedBorrow address for `mint`, empty if no fixed
+    /// borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), mint.key().as_ref()],
+        bump,
+    )]
+    fixed_borrow: UncheckedAccount<'info>,
+    /// CHECK: the user's FixedBorrow address for `target_mint`, empty if no
+    /// fixed borrow was ever opened there.
+    #[account(
+        mut,
+        seeds = [SEED_FIXED_BORROW, user.key().as_ref(), target_mint.key().as_ref()],
+        bump,
+    )]
+    target_fixed_borrow: UncheckedAccount<'info>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[user.emode_category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+
+    mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Box<Account<'info, Bank>>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program
+    )]
+    treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump = bank.collateral_mint_bump,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, mint.key().as_ref()],
+        bump = bank.collateral_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_vault,
+        token::token_program = token_program
+    )]
+    collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    user_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    price_update: Box<Account<'info, PriceUpdateV2>>,
+
+    target_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, target_mint.key().as_ref()],
+        bump = target_bank.bump,
+        constraint = target_bank.mint != mint.key() @ ErrorCode::InvalidCollateralBank,
+        constraint = user.is_pair(&target_bank.mint, &mint.key()) @ ErrorCode::InvalidCollateralBank,
+    )]
+    target_bank: Box<Account<'info, Bank>>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, target_mint.key().as_ref()],
+        bump = target_bank.treasury_bump,
+        token::mint = target_mint,
+        token::authority = target_treasury,
+        token::token_program = token_program
+    )]
+    target_treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_MINT, target_mint.key().as_ref()],
+        bump = target_bank.collateral_mint_bump,
+    )]
+    target_collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, target_mint.key().as_ref()],
+        bump = target_bank.collateral_vault_bump,
+        token::mint = target_collateral_mint,
+        token::authority = target_collateral_vault,
+        token::token_program = token_program
+    )]
+    target_collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = target_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    target_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    target_price_update: Box<Account<'info, PriceUpdateV2>>,
+
+    /// CHECK: only invoked, and only when it is the bank's configured swap program.
+    #[account(
+        executable,
+        constraint = bank.swap_program != Pubkey::default()
+            && swap_program.key() == bank.swap_program @ ErrorCode::SwapProgramNotAllowed,
+    )]
+    swap_program: UncheckedAccount<'info>,
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> SwapCollateral<'info> {
+    /// Flash-withdraws `amount` of the `mint` deposit (`u64::MAX` for all of
+    /// it), swaps it through `swap_program` and deposits the proceeds into
+    /// `target_bank`. The position only has to be healthy at the end. Reward
+    /// pairs for `bank`, then `target_bank`, lead the remaining accounts,
+    /// followed by `withdraw_hook_accounts` transfer-hook accounts for `mint`,
+    /// `deposit_hook_accounts` for `target_mint` and the swap accounts.
+    pub fn swap_collateral(
+        &mut self,
+        amount: u64,
+        min_amount_out: u64,
+        swap_data: Vec<u8>,
+        withdraw_hook_accounts: u8,
+        deposit_hook_accounts: u8,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let (mut rewards, remaining_accounts) =
+            self.settle_positions(remaining_accounts, &clock)?;
+        let (withdraw_hook_accounts, remaining_accounts) =
+            take_hook_accounts(remaining_accounts, withdraw_hook_accounts)?;
+        let (deposit_hook_accounts, remaining_accounts) =
+            take_hook_accounts(remaining_accounts, deposit_hook_accounts)?;
+
+        // Isolated debt is booked against the collateral bank it was borrowed
+        // under, so moving collateral in or out of one would strand it.
+        let has_debt = self.user.borrowed_usdc_shares != 0 || self.user.borrowed_sol_shares != 0;
+        if has_debt
+            && (self.bank.risk_tier == RiskTier::Isolated
+                || self.target_bank.risk_tier == RiskTier::Isolated)
+        {
+            return Err(ErrorCode::IsolatedCollateralSwap.into());
+        }
+
+        self.flash_withdraw(amount, withdraw_hook_accounts)?;
+        let received = self.swap(min_amount_out, swap_data, remaining_accounts)?;
+        self.deposit_target(received, deposit_hook_accounts)?;
+
+        self.check_health(&clock)?;
+        self.user.last_updated = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards.0, &self.user)?;
+        settle_rewards(&mut rewards.1, &self.user)
+    }
+
+    /// Accrues both banks and their reward pools, taken in that order from
+    /// the front of `remaining_accounts`, then settles the user's fixed
+    /// borrows in them. Returns the reward positions and the swap accounts.
+    fn settle_positions(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        clock: &Clock,
+    ) -> Result<(
+        (RewardPositions<'info>, RewardPositions<'info>),
+        &'info [AccountInfo<'info>],
+    )> {
+        self.bank.accrue_interest(clock)?;
+        self.target_bank.accrue_interest(clock)?;
+
+        let (rewards, remaining_accounts) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+        let (target_rewards, remaining_accounts) = accrue_rewards(
+            &self.target_bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        FixedBorrow::settle_at(
+            &self.fixed_borrow,
+            &mut self.bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+        FixedBorrow::settle_at(
+            &self.target_fixed_borrow,
+            &mut self.target_bank,
+            &mut self.user,
+            clock.unix_timestamp,
+        )?;
+
+        Ok(((rewards, target_rewards), remaining_accounts))
+    }
+
+    /// Withdraws `amount` of the user's `mint` deposit into the signer's account.
+    fn flash_withdraw(
+        &mut self,
+        amount: u64,
+        hook_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let deposited_shares = self.user.deposited_shares(&self.mint.key());
+        let user_value = self.bank.deposit_amount_for(deposited_shares)?;
+
+        let amount = if amount == u64::MAX {
+            user_value
+        } else {
+            amount
+        };
+
+        if user_value < amount {
+            return Err(ErrorCode::InsufficientFunds.into());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: self.treasury.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.user_ata.to_account_info(),
+            authority: self.treasury.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
+            &[self.bank.treasury_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(hook_accounts.to_vec());
+
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        let shares_to_remove = self.bank.debit_deposit(&mut self.user, amount)?;
+
+        let cpi_accounts = Burn {
+            mint: self.collateral_mint.to_account_info(),
+            from: self.collateral_vault.to_account_info(),
+            authority: self.collateral_vault.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_COLLATERAL_VAULT,
+            self.bank.mint.as_ref(),
+            &[self.bank.collateral_vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        burn(cpi_ctx, shares_to_remove)?;
+
+        Ok(())
+    }
+
+    /// Runs the caller's swap instruction and returns how much of
+    /// `target_mint` it delivered to the signer.
+    fn swap(
+        &mut self,
+        min_amount_out: u64,
+        swap_data: Vec<u8>,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<u64> {
+        let balance_before = self.target_ata.amount;
+
+        invoke_swap(&self.swap_program, remaining_accounts, swap_data)?;
+
+        self.target_ata.reload()?;
+        let received = self
+            .target_ata
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::SlippageExceeded)?;
+
+        if received < min_amount_out {
+            return Err(ErrorCode::SlippageExceeded.into());
+        }
+
+        Ok(received)
+    }
+
+    fn deposit_target(
+        &mut self,
+        amount: u64,
+        hook_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            from: self.target_ata.to_account_info(),
+            mint: self.target_mint.to_account_info(),
+            to: self.target_treasury.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(hook_accounts.to_vec());
+
+        let balance_before = self.target_treasury.amount;
+
+        transfer_checked_with_hook(
+            cpi_ctx,
+            &self.target_bank,
+            amount,
+            self.target_mint.decimals,
+        )?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.target_treasury.reload()?;
+        let amount = self
+            .target_treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let user_shares = self.target_bank.credit_deposit(&mut self.user, amount)?;
+
+        let cpi_accounts = MintTo {
+            mint: self.target_collateral_mint.to_account_info(),
+            to: self.target_collateral_vault.to_account_info(),
+            authority: self.target_bank.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_BANK_ACCOUNT,
+            self.target_bank.mint.as_ref(),
+            &[self.target_bank.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        mint_to(cpi_ctx, user_shares)?;
+
+        Ok(())
+    }
+
+    /// Repays up to `amount` of the user's `target_mint` debt from the signer's
+    /// account, leaving any surplus there, and returns how much was repaid.
+    fn repay_target(
+        &mut self,
+        amount: u64,
+        hook_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<u64> {
+        let borrowed_shares = self.user.borrowed_shares(&self.target_mint.key());
+        let user_value = self.target_bank.borrow_amount_for(borrowed_shares)?;
+        let amount = amount.min(user_value);
+
+        let cpi_accounts = TransferChecked {
+            from: self.target_ata.to_account_info(),
+            mint: self.target_mint.to_account_info(),
+            to: self.target_treasury.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(hook_accounts.to_vec());
+
+        let balance_before = self.target_treasury.amount;
+
+        transfer_checked_with_hook(
+            cpi_ctx,
+            &self.target_bank,
+            amount,
+            self.target_mint.decimals,
+        )?;
+
+        // Transfer-fee mints deliver less than `amount`, so only credit what arrived.
+        self.target_treasury.reload()?;
+        let amount = self
+            .target_treasury
+            .amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        self.target_bank.debit_borrow(&mut self.user, amount)?;
+
+        Ok(amount)
+    }
+
+    /// Revalues the position from both banks and rejects it if unhealthy.
+    fn check_health(&mut self, clock: &Clock) -> Result<()> {
+        let price = self.bank.price(&self.price_update, clock)?;
+        let target_price = self.target_bank.price(&self.target_price_update, clock)?;
+
+        let positions = [
+            (Bank::clone(&self.bank), price),
+            (Bank::clone(&self.target_bank), target_price),
+        ];
+        refresh_with_emode(&mut self.user, &positions, &self.emode_category, clock.slot)?;
+
+        if self.user.borrowed_value > self.user.allowed_borrow_value {
+            return Err(ErrorCode::Undercollateralized.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct RepayWithCollateral<'info> {
+    swap: SwapCollateral<'info>,
+}
+
+impl<'info> RepayWithCollateral<'info> {
+    /// Flash-withdraws `amount` of the `mint` deposit (`u64::MAX` for all of
+    /// it), swaps it into `target_mint` and repays that debt with the proceeds.
+    /// Anything beyond the outstanding debt stays in the signer's account.
+    /// Takes its remaining accounts as `swap_collateral` does.
+    pub fn repay_with_collateral(
+        &mut self,
+        amount: u64,
+        min_amount_out: u64,
+        swap_data: Vec<u8>,
+        withdraw_hook_accounts: u8,
+        repay_hook_accounts: u8,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let swap = &mut self.swap;
+
+        let clock = Clock::get()?;
+        let (mut rewards, remaining_accounts) =
+            swap.settle_positions(remaining_accounts, &clock)?;
+        let (withdraw_hook_accounts, remaining_accounts) =
+            take_hook_accounts(remaining_accounts, withdraw_hook_accounts)?;
+        let (repay_hook_accounts, remaining_accounts) =
+            take_hook_accounts(remaining_accounts, repay_hook_accounts)?;
+
+        swap.flash_withdraw(amount, withdraw_hook_accounts)?;
+        let received = swap.swap(min_amount_out, swap_data, remaining_accounts)?;
+        let repaid = swap.repay_target(received, repay_hook_accounts)?;
+
+        let isolated_debt = swap.target_bank.isolated_debt_for(repaid)?;
+        release_isolation(
+            &mut swap.user,
+            [Some(&mut **swap.bank), Some(&mut **swap.target_bank)],
+            isolated_debt,
+        )?;
+
+        swap.check_health(&clock)?;
+        swap.user.last_updated = clock.unix_timestamp;
+        swap.user.last_updated_borrow = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards.0, &swap.user)?;
+        settle_rewards(&mut rewards.1, &swap.user)
+    }
+}
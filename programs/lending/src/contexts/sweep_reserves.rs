@@ -0,0 +1,103 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::{
+    transfer_checked_with_hook, Bank, SEED_BANK_ACCOUNT, SEED_FEE_VAULT, SEED_INSURANCE_VAULT,
+    SEED_TREASURY_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct SweepReserves<'info> {
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [SEED_TREASURY_ACCOUNT, mint.key().as_ref()],
+        bump = bank.treasury_bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program
+    )]
+    treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_INSURANCE_VAULT, mint.key().as_ref()],
+        bump = bank.insurance_vault_bump,
+        token::mint = mint,
+        token::authority = insurance_vault,
+        token::token_program = token_program
+    )]
+    insurance_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_FEE_VAULT, mint.key().as_ref()],
+        bump = bank.fee_vault_bump,
+        token::mint = mint,
+        token::authority = fee_vault,
+        token::token_program = token_program
+    )]
+    fee_vault: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SweepReserves<'info> {
+    /// Moves interest set aside for insurance into the insurance vault and
+    /// accrued borrow fees into the fee vault, as far as the treasury's idle
+    /// liquidity allows.
+    pub fn sweep_reserves(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+
+        let insurance = self.bank.insurance_reserve.min(self.treasury.amount);
+        let fees = self
+            .bank
+            .protocol_fees
+            .min(self.treasury.amount - insurance);
+
+        let seeds = &[
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
+            &[self.bank.treasury_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        for (to, amount) in [
+            (self.insurance_vault.to_account_info(), insurance),
+            (self.fee_vault.to_account_info(), fees),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+
+            let cpi_accounts = TransferChecked {
+                from: self.treasury.to_account_info(),
+                mint: self.mint.to_account_info(),
+                to,
+                authority: self.treasury.to_account_info(),
+            };
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            )
+            .with_remaining_accounts(remaining_accounts.to_vec());
+
+            transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+        }
+
+        self.bank.insurance_reserve -= insurance;
+        self.bank.protocol_fees -= fees;
+
+        Ok(())
+    }
+}
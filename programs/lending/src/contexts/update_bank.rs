@@ -0,0 +1,111 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{
+    error::ErrorCode, Bank, RiskTier, BPS_DENOMINATOR, MAX_TRANSFER_HOOK_PROGRAMS,
+    SEED_BANK_ACCOUNT,
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct UpdateBankParams {
+    pub risk_tier: Option<RiskTier>,
+    pub debt_ceiling: Option<u64>,
+    pub borrowable_in_isolation: Option<bool>,
+    pub transfer_hook_programs: Option<Vec<Pubkey>>,
+    pub swap_program: Option<Pubkey>,
+    pub liquidation_bonus: Option<u64>,
+    pub max_liquidation_bonus: Option<u64>,
+    pub liquidation_bonus_ramp: Option<u64>,
+    pub liquidation_close_factor: Option<u64>,
+    pub liquidation_fee: Option<u64>,
+    pub insurance_fee: Option<u64>,
+    pub borrow_fee_bps: Option<u64>,
+    pub host_fee_bps: Option<u64>,
+    pub fixed_rate_premium: Option<u64>,
+    pub max_fixed_term: Option<i64>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBank<'info> {
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+        constraint = bank.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    bank: Account<'info, Bank>,
+}
+
+impl<'info> UpdateBank<'info> {
+    pub fn update_bank(&mut self, params: UpdateBankParams) -> Result<()> {
+        if let Some(risk_tier) = params.risk_tier {
+            self.bank.risk_tier = risk_tier;
+        }
+        if let Some(debt_ceiling) = params.debt_ceiling {
+            self.bank.debt_ceiling = debt_ceiling;
+        }
+        if let Some(borrowable_in_isolation) = params.borrowable_in_isolation {
+            self.bank.borrowable_in_isolation = borrowable_in_isolation;
+        }
+        if let Some(programs) = params.transfer_hook_programs {
+            if programs.len() > MAX_TRANSFER_HOOK_PROGRAMS {
+                return Err(ErrorCode::TooManyTransferHookPrograms.into());
+            }
+
+            self.bank.transfer_hook_programs = [Pubkey::default(); MAX_TRANSFER_HOOK_PROGRAMS];
+            self.bank.transfer_hook_programs[..programs.len()].copy_from_slice(&programs);
+        }
+        if let Some(swap_program) = params.swap_program {
+            self.bank.swap_program = swap_program;
+        }
+        if let Some(liquidation_bonus) = params.liquidation_bonus {
+            self.bank.liquidation_bonus = checked_bps(liquidation_bonus)?;
+        }
+        if let Some(max_liquidation_bonus) = params.max_liquidation_bonus {
+            self.bank.max_liquidation_bonus = checked_bps(max_liquidation_bonus)?;
+        }
+        if let Some(liquidation_bonus_ramp) = params.liquidation_bonus_ramp {
+            self.bank.liquidation_bonus_ramp = checked_bps(liquidation_bonus_ramp)?;
+        }
+        if self.bank.liquidation_bonus > self.bank.max_liquidation_bonus {
+            return Err(ErrorCode::InvalidLiquidationBonus.into());
+        }
+        if let Some(liquidation_close_factor) = params.liquidation_close_factor {
+            self.bank.liquidation_close_factor = checked_bps(liquidation_close_factor)?;
+        }
+        if let Some(liquidation_fee) = params.liquidation_fee {
+            self.bank.liquidation_fee = checked_bps(liquidation_fee)?;
+        }
+        if let Some(insurance_fee) = params.insurance_fee {
+            self.bank.accrue_interest(&Clock::get()?)?;
+            self.bank.insurance_fee = checked_bps(insurance_fee)?;
+        }
+        if let Some(borrow_fee_bps) = params.borrow_fee_bps {
+            self.bank.borrow_fee_bps = checked_bps(borrow_fee_bps)?;
+        }
+        if let Some(host_fee_bps) = params.host_fee_bps {
+            self.bank.host_fee_bps = checked_bps(host_fee_bps)?;
+        }
+        if let Some(fixed_rate_premium) = params.fixed_rate_premium {
+            self.bank.fixed_rate_premium = checked_bps(fixed_rate_premium)?;
+        }
+        if let Some(max_fixed_term) = params.max_fixed_term {
+            if max_fixed_term < 0 {
+                return Err(ErrorCode::InvalidFixedTerm.into());
+            }
+            self.bank.max_fixed_term = max_fixed_term;
+        }
+
+        Ok(())
+    }
+}
+
+fn checked_bps(bps: u64) -> Result<u64> {
+    if bps > BPS_DENOMINATOR {
+        return Err(ErrorCode::InvalidBps.into());
+    }
+
+    Ok(bps)
+}
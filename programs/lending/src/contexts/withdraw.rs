@@ -1,13 +1,16 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{burn, Burn, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
 use crate::{
-    error::ErrorCode, Bank, User, SEED_BANK_ACCOUNT, SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT,
+    accrue_rewards, apply_bps, error::ErrorCode, settle_rewards, transfer_checked_with_hook,
+    unwrap_sol, Bank, EmodeCategory, User, PERMISSION_WITHDRAW, SEED_BANK_ACCOUNT,
+    SEED_COLLATERAL_MINT, SEED_COLLATERAL_VAULT, SEED_EMODE_CATEGORY, SEED_TREASURY_ACCOUNT,
+    SEED_USER_ACCOUNT, SEED_WSOL_ACCOUNT,
 };
 
 #[derive(Accounts)]
@@ -32,18 +35,47 @@ pub struct Withdraw<'info> {
     treasury: InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
-        seeds = [SEED_USER_ACCOUNT, signer.key().as_ref()],
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump = bank.collateral_mint_bump,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, mint.key().as_ref()],
+        bump = bank.collateral_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_vault,
+        token::token_program = token_program
+    )]
+    collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            user.owner.as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
         bump = user.bump,
+        constraint = user.can_act(&signer.key(), PERMISSION_WITHDRAW) @ ErrorCode::NotDelegated,
     )]
     user: Account<'info, User>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[user.emode_category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+    /// CHECK: only used as the payout ATA authority, pinned to `user.owner`.
+    #[account(address = user.owner)]
+    owner: UncheckedAccount<'info>,
     #[account(
         init_if_needed,
         payer = signer,
         associated_token::mint = mint,
-        associated_token::authority = signer,
+        associated_token::authority = owner,
         associated_token::token_program = token_program
     )]
     user_ata: InterfaceAccount<'info, TokenAccount>,
+    price_update: Account<'info, PriceUpdateV2>,
 
     token_program: Interface<'info, TokenInterface>,
     associated_token_program: Program<'info, AssociatedToken>,
@@ -51,27 +83,53 @@ pub struct Withdraw<'info> {
 }
 
 impl<'info> Withdraw<'info> {
-    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
-        let deposited_value: u64;
-
-        if self.mint.to_account_info().key() == self.user.mint_usdc {
-            deposited_value = self.user.deposited_usdc;
+    /// Withdraws `amount` of the user's collateral (`u64::MAX` for all of it).
+    /// Receipts held in a wallet are redeemed with `redeem_receipt` instead.
+    pub fn withdraw(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, remaining_accounts) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        let deposited_shares = self.user.deposited_shares(&self.mint.key());
+        let user_value = self.bank.deposit_amount_for(deposited_shares)?;
+
+        // `u64::MAX` withdraws everything the shares are worth after accrual.
+        let amount = if amount == u64::MAX {
+            user_value
         } else {
-            deposited_value = self.user.deposited_sol;
+            amount
+        };
+
+        if user_value < amount {
+            return Err(ErrorCode::InsufficientFunds.into());
         }
 
-        let time_diff = self.user.last_updated - Clock::get()?.unix_timestamp;
-        self.bank.total_deposit = (self.bank.total_deposit as f64
-            * E.powf(self.bank.interest_rate as f64 * time_diff as f64))
-            as u64;
+        if self.user.is_stale(clock.slot) {
+            return Err(ErrorCode::UserStale.into());
+        }
 
-        let value_per_share =
-            self.bank.total_deposit as f64 / self.bank.total_deposit_shares as f64;
+        let price = self.bank.price(&self.price_update, &clock)?;
+        let withdraw_value = self.bank.market_value(amount, &price)?;
 
-        let user_value = (deposited_value as f64 / value_per_share) as u64;
+        let (max_ltv, liquidation_threshold) = self
+            .user
+            .collateral_params(&self.bank, self.emode_category.as_deref())?;
+        let allowed_borrow_value = self
+            .user
+            .allowed_borrow_value
+            .saturating_sub(apply_bps(withdraw_value, max_ltv)?);
 
-        if user_value < amount {
-            return Err(ErrorCode::InsufficientFunds.into());
+        if self.user.borrowed_value > allowed_borrow_value {
+            return Err(ErrorCode::Undercollateralized.into());
         }
 
         let cpi_accounts = TransferChecked {
@@ -82,8 +140,8 @@ impl<'info> Withdraw<'info> {
         };
 
         let seeds = &[
-            &SEED_TREASURY_ACCOUNT[..],
-            &self.bank.mint.as_ref(),
+            SEED_TREASURY_ACCOUNT,
+            self.bank.mint.as_ref(),
             &[self.bank.treasury_bump],
         ];
         let signer_seeds = &[&seeds[..]];
@@ -92,27 +150,95 @@ impl<'info> Withdraw<'info> {
             self.token_program.to_account_info(),
             cpi_accounts,
             signer_seeds,
-        );
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
 
-        transfer_checked(cpi_ctx, amount, self.mint.decimals)?;
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
 
-        let shares_to_remove = (amount as f64 / self.bank.total_deposit as f64)
-            * self.bank.total_deposit_shares as f64;
+        let shares_to_remove = self.bank.debit_deposit(&mut self.user, amount)?;
 
-        match self.mint.to_account_info().key() {
-            key if key == self.user.mint_usdc => {
-                self.user.deposited_usdc -= amount;
-                self.user.deposited_usdc_shares -= shares_to_remove as u64;
-            }
-            _ => {
-                self.user.deposited_sol -= amount;
-                self.user.deposited_sol_shares -= shares_to_remove as u64;
-            }
-        }
+        let cpi_accounts = Burn {
+            mint: self.collateral_mint.to_account_info(),
+            from: self.collateral_vault.to_account_info(),
+            authority: self.collateral_vault.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_COLLATERAL_VAULT,
+            self.bank.mint.as_ref(),
+            &[self.bank.collateral_vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        burn(cpi_ctx, shares_to_remove)?;
 
-        self.bank.total_deposit -= amount;
-        self.bank.total_deposit_shares -= shares_to_remove as u64;
+        self.user.deposited_value = self.user.deposited_value.saturating_sub(withdraw_value);
+        self.user.allowed_borrow_value = allowed_borrow_value;
+        self.user.unhealthy_borrow_value = self
+            .user
+            .unhealthy_borrow_value
+            .saturating_sub(apply_bps(withdraw_value, liquidation_threshold)?);
+        self.user.last_updated = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards, &self.user)?;
 
         Ok(())
     }
 }
+
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    withdraw: Withdraw<'info>,
+    /// Temporary account the lamports are unwrapped through, closed before the
+    /// instruction ends.
+    #[account(
+        init,
+        payer = withdraw.signer,
+        seeds = [SEED_WSOL_ACCOUNT, withdraw.signer.key().as_ref()],
+        bump,
+        token::mint = withdraw.mint,
+        token::authority = withdraw.signer,
+        token::token_program = token_program
+    )]
+    wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = withdraw.token_program.key())]
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> WithdrawSol<'info> {
+    /// Withdraws `amount` from the SOL bank and unwraps what arrived to the
+    /// signer.
+    pub fn withdraw_sol(
+        &mut self,
+        amount: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let withdraw = &mut self.withdraw;
+
+        // Unwrapping moves wSOL out of the owner's account, which only the owner can sign.
+        if withdraw.signer.key() != withdraw.user.owner {
+            return Err(ErrorCode::NotDelegated.into());
+        }
+
+        let balance_before = withdraw.user_ata.amount;
+        withdraw.withdraw(amount, remaining_accounts)?;
+        withdraw.user_ata.reload()?;
+
+        unwrap_sol(
+            &withdraw.mint.to_account_info(),
+            withdraw.signer.to_account_info(),
+            self.wsol_account.to_account_info(),
+            withdraw.user_ata.to_account_info(),
+            withdraw.token_program.to_account_info(),
+            withdraw.user_ata.amount - balance_before,
+        )
+    }
+}
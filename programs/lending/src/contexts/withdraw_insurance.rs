@@ -0,0 +1,91 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    error::ErrorCode, transfer_checked_with_hook, Bank, SEED_BANK_ACCOUNT, SEED_INSURANCE_VAULT,
+};
+
+#[derive(Accounts)]
+pub struct WithdrawInsurance<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+        constraint = bank.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [SEED_INSURANCE_VAULT, mint.key().as_ref()],
+        bump = bank.insurance_vault_bump,
+        token::mint = mint,
+        token::authority = insurance_vault,
+        token::token_program = token_program
+    )]
+    insurance_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    signer_ata: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> WithdrawInsurance<'info> {
+    /// Executes the withdrawal queued by `request_insurance_withdrawal` once
+    /// its timelock has passed.
+    pub fn withdraw_insurance(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let amount = self.bank.insurance_withdrawal_amount;
+        if amount == 0 {
+            return Err(ErrorCode::NoInsuranceWithdrawal.into());
+        }
+
+        let clock = Clock::get()?;
+        if clock.unix_timestamp < self.bank.insurance_withdrawal_unlocks_at {
+            return Err(ErrorCode::InsuranceWithdrawalLocked.into());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: self.insurance_vault.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.signer_ata.to_account_info(),
+            authority: self.insurance_vault.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_INSURANCE_VAULT,
+            self.bank.mint.as_ref(),
+            &[self.bank.insurance_vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(remaining_accounts.to_vec());
+
+        transfer_checked_with_hook(cpi_ctx, &self.bank, amount, self.mint.decimals)?;
+
+        self.bank.insurance_withdrawal_amount = 0;
+        self.bank.insurance_withdrawal_unlocks_at = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    accrue_rewards, apply_bps, error::ErrorCode, settle_rewards, Bank, EmodeCategory, User,
+    SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT, SEED_COLLATERAL_VAULT, SEED_EMODE_CATEGORY,
+    SEED_USER_ACCOUNT,
+};
+
+#[derive(Accounts)]
+pub struct WithdrawReceiptCollateral<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_BANK_ACCOUNT, mint.key().as_ref()],
+        bump = bank.bump,
+    )]
+    bank: Account<'info, Bank>,
+    #[account(
+        seeds = [SEED_COLLATERAL_MINT, mint.key().as_ref()],
+        bump = bank.collateral_mint_bump,
+    )]
+    collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [SEED_COLLATERAL_VAULT, mint.key().as_ref()],
+        bump = bank.collateral_vault_bump,
+        token::mint = collateral_mint,
+        token::authority = collateral_vault,
+        token::token_program = token_program
+    )]
+    collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            SEED_USER_ACCOUNT,
+            signer.key().as_ref(),
+            &user.sub_account.to_le_bytes(),
+        ],
+        bump = user.bump,
+    )]
+    user: Account<'info, User>,
+    #[account(
+        seeds = [SEED_EMODE_CATEGORY, &[user.emode_category]],
+        bump = emode_category.bump,
+    )]
+    emode_category: Option<Account<'info, EmodeCategory>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = collateral_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    user_collateral_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    price_update: Account<'info, PriceUpdateV2>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> WithdrawReceiptCollateral<'info> {
+    /// Unlocks `shares` receipt tokens from the user's position to the signer's
+    /// wallet, as long as the remaining collateral still covers the debt.
+    pub fn withdraw_receipt_collateral(
+        &mut self,
+        shares: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        self.bank.accrue_interest(&clock)?;
+        let (mut rewards, _) = accrue_rewards(
+            &self.bank,
+            &self.user,
+            remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        let deposited_shares = self.user.deposited_shares(&self.mint.key());
+        if shares > deposited_shares {
+            return Err(ErrorCode::InsufficientFunds.into());
+        }
+
+        if self.user.is_stale(clock.slot) {
+            return Err(ErrorCode::UserStale.into());
+        }
+
+        let amount = self.bank.deposit_amount_for(shares)?;
+
+        let price = self.bank.price(&self.price_update, &clock)?;
+        let withdraw_value = self.bank.market_value(amount, &price)?;
+
+        let (max_ltv, liquidation_threshold) = self
+            .user
+            .collateral_params(&self.bank, self.emode_category.as_deref())?;
+        let allowed_borrow_value = self
+            .user
+            .allowed_borrow_value
+            .saturating_sub(apply_bps(withdraw_value, max_ltv)?);
+
+        if self.user.borrowed_value > allowed_borrow_value {
+            return Err(ErrorCode::Undercollateralized.into());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: self.collateral_vault.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            to: self.user_collateral_ata.to_account_info(),
+            authority: self.collateral_vault.to_account_info(),
+        };
+
+        let seeds = &[
+            SEED_COLLATERAL_VAULT,
+            self.bank.mint.as_ref(),
+            &[self.bank.collateral_vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+
+        transfer_checked(cpi_ctx, shares, self.collateral_mint.decimals)?;
+
+        self.bank.unlock_receipts(&mut self.user, shares, amount)?;
+
+        self.user.deposited_value = self.user.deposited_value.saturating_sub(withdraw_value);
+        self.user.allowed_borrow_value = allowed_borrow_value;
+        self.user.unhealthy_borrow_value = self
+            .user
+            .unhealthy_borrow_value
+            .saturating_sub(apply_bps(withdraw_value, liquidation_threshold)?);
+        self.user.last_updated = clock.unix_timestamp;
+
+        settle_rewards(&mut rewards, &self.user)
+    }
+}
@@ -10,4 +10,102 @@ pub enum ErrorCode {
     OverRepay,
     #[msg("User is not undercollateralized.")]
     NotUndercollateralized,
+    #[msg("Math Overflow")]
+    MathOverflow,
+    #[msg("User must be refreshed in the current slot")]
+    UserStale,
+    #[msg("Invalid accounts passed to refresh user")]
+    InvalidRefreshAccounts,
+    #[msg("Invalid oracle price")]
+    InvalidPrice,
+    #[msg("Position would be undercollateralized")]
+    Undercollateralized,
+    #[msg("Bank has no oracle feed set by the market admin")]
+    OracleNotSet,
+    #[msg("Signer is not the market admin")]
+    NotMarketAdmin,
+    #[msg("Signer is not the bank authority")]
+    Unauthorized,
+    #[msg("Asset cannot be borrowed against isolated collateral")]
+    NotBorrowableInIsolation,
+    #[msg("Isolated collateral debt ceiling exceeded")]
+    DebtCeilingExceeded,
+    #[msg("Isolated borrow asset cannot be borrowed alongside other debt")]
+    IsolatedBorrowConflict,
+    #[msg("Collateral bank does not match the user's other position")]
+    InvalidCollateralBank,
+    #[msg("Debt is already booked against another isolated bank")]
+    IsolatedCollateralConflict,
+    #[msg("User's isolated collateral bank must be passed")]
+    IsolatedBankRequired,
+    #[msg("Invalid e-mode category")]
+    InvalidEmodeCategory,
+    #[msg("E-mode parameters must satisfy max_ltv <= liquidation_threshold <= 10000")]
+    InvalidEmodeParameters,
+    #[msg("Bank's mint is not one of the user's position mints")]
+    UnsupportedMint,
+    #[msg("Mint has an unsupported Token-2022 extension")]
+    UnsupportedMintExtension,
+    #[msg("Mint transfer hook program is not whitelisted")]
+    TransferHookNotAllowed,
+    #[msg("Too many transfer hook programs")]
+    TooManyTransferHookPrograms,
+    #[msg("Mint is not the native SOL mint")]
+    NotNativeMint,
+    #[msg("User still has deposits or borrows")]
+    UserNotEmpty,
+    #[msg("Signer is not allowed to act for this user")]
+    NotDelegated,
+    #[msg("User does not accept repayments from third parties")]
+    ThirdPartyRepaysDisabled,
+    #[msg("User does not accept deposits from third parties")]
+    ThirdPartyDepositsDisabled,
+    #[msg("Swap program is not allowed for this bank")]
+    SwapProgramNotAllowed,
+    #[msg("Swap output is below the minimum")]
+    SlippageExceeded,
+    #[msg("Fewer transfer hook accounts were passed than declared")]
+    InvalidHookAccounts,
+    #[msg("Isolated collateral cannot be swapped while borrowing")]
+    IsolatedCollateralSwap,
+    #[msg("Basis-point value is out of range")]
+    InvalidBps,
+    #[msg("Collateral and borrowed mints must differ")]
+    InvalidLiquidationMints,
+    #[msg("Liquidation amount rounds to zero")]
+    LiquidationTooSmall,
+    #[msg("Minimum liquidation bonus exceeds the maximum")]
+    InvalidLiquidationBonus,
+    #[msg("User still has collateral or no debt to write off")]
+    UserNotInsolvent,
+    #[msg("No insurance withdrawal is pending")]
+    NoInsuranceWithdrawal,
+    #[msg("Insurance withdrawal is still timelocked")]
+    InsuranceWithdrawalLocked,
+    #[msg("Users cannot refer themselves")]
+    SelfReferral,
+    #[msg("User is already attributed to another referrer")]
+    ReferrerMismatch,
+    #[msg("No referrer fees to claim")]
+    NoReferrerFees,
+    #[msg("User's attributed referrer account must be passed")]
+    ReferrerRequired,
+    #[msg(
+        "Reward accounts must be a (reward pool, user reward) pair for each of the bank's pools"
+    )]
+    InvalidRewardAccounts,
+    #[msg("No rewards to claim")]
+    NoRewards,
+    #[msg("Bank already has the maximum number of reward pools")]
+    TooManyRewardPools,
+    #[msg("Claim the user's outstanding rewards before closing it")]
+    UnclaimedRewards,
+    #[msg("Fixed-rate term is zero or longer than the bank allows")]
+    InvalidFixedTerm,
+    #[msg("Quoted fixed rate exceeds the maximum")]
+    FixedRateExceeded,
+    #[msg("User already has an active fixed-rate borrow on this bank")]
+    FixedBorrowActive,
+    #[msg("Fixed borrow account is not owned by this program")]
+    InvalidFixedBorrow,
 }
@@ -0,0 +1,17 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct UserClosed {
+    pub user: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct BadDebtSocialized {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub debt: u64,
+    pub covered_by_insurance: u64,
+    pub covered_by_reserves: u64,
+    pub socialized: u64,
+}
@@ -3,11 +3,15 @@ use anchor_lang::prelude::*;
 pub mod constants;
 pub mod contexts;
 pub mod error;
+pub mod events;
 pub mod states;
+pub mod utils;
 
 pub use constants::*;
 pub use contexts::*;
+pub use events::*;
 pub use states::*;
+pub use utils::*;
 
 declare_id!("8iZGbJw7yWA4znvCcnz4VGKhdnzRwGPiU5BjLpV539Kc");
 
@@ -15,32 +19,335 @@ declare_id!("8iZGbJw7yWA4znvCcnz4VGKhdnzRwGPiU5BjLpV539Kc");
 pub mod lending {
     use super::*;
 
+    pub fn init_market(ctx: Context<InitMarket>) -> Result<()> {
+        ctx.accounts.init_market(&ctx.bumps)
+    }
+
     pub fn init_bank(
         ctx: Context<InitBank>,
         liquidation_threshold: u64,
         max_ltv: u64,
+        allowed_hook_programs: Vec<Pubkey>,
+    ) -> Result<()> {
+        ctx.accounts.init_bank(
+            liquidation_threshold,
+            max_ltv,
+            allowed_hook_programs,
+            &ctx.bumps,
+        )
+    }
+
+    pub fn set_bank_oracle(ctx: Context<SetBankOracle>, feed_id: [u8; 32]) -> Result<()> {
+        ctx.accounts.set_bank_oracle(feed_id)
+    }
+
+    pub fn update_bank(ctx: Context<UpdateBank>, params: UpdateBankParams) -> Result<()> {
+        ctx.accounts.update_bank(params)
+    }
+
+    pub fn init_emode_category(
+        ctx: Context<InitEmodeCategory>,
+        id: u8,
+        max_ltv: u64,
+        liquidation_threshold: u64,
+        liquidation_bonus: u64,
+    ) -> Result<()> {
+        ctx.accounts.init_emode_category(
+            id,
+            max_ltv,
+            liquidation_threshold,
+            liquidation_bonus,
+            &ctx.bumps,
+        )
+    }
+
+    pub fn set_bank_emode(ctx: Context<SetBankEmode>, category: u8) -> Result<()> {
+        ctx.accounts.set_bank_emode(category)
+    }
+
+    pub fn init_user(
+        ctx: Context<InitUser>,
+        mint_usdc: Pubkey,
+        mint_sol: Pubkey,
+        sub_account: u16,
+    ) -> Result<()> {
+        ctx.accounts
+            .init_user(mint_usdc, mint_sol, sub_account, &ctx.bumps)
+    }
+
+    pub fn set_delegate(
+        ctx: Context<SetDelegate>,
+        delegate: Pubkey,
+        permissions: u8,
+    ) -> Result<()> {
+        ctx.accounts.set_delegate(delegate, permissions)
+    }
+
+    pub fn set_third_party_deposits(
+        ctx: Context<SetThirdPartyDeposits>,
+        allowed: bool,
+    ) -> Result<()> {
+        ctx.accounts.set_third_party_deposits(allowed)
+    }
+
+    pub fn set_third_party_repays(ctx: Context<SetThirdPartyRepays>, allowed: bool) -> Result<()> {
+        ctx.accounts.set_third_party_repays(allowed)
+    }
+
+    pub fn close_user<'info>(ctx: Context<'_, '_, 'info, 'info, CloseUser<'info>>) -> Result<()> {
+        ctx.accounts.close_user(ctx.remaining_accounts)
+    }
+
+    pub fn refresh_bank(ctx: Context<RefreshBank>) -> Result<()> {
+        ctx.accounts.refresh_bank()
+    }
+
+    pub fn refresh_user<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefreshUser<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.refresh_user(ctx.remaining_accounts)
+    }
+
+    pub fn set_emode<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SetEmode<'info>>,
+        category: u8,
+    ) -> Result<()> {
+        ctx.accounts.set_emode(category, ctx.remaining_accounts)
+    }
+
+    pub fn deposit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.deposit(amount, false, ctx.remaining_accounts)
+    }
+
+    pub fn deposit_for<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositFor<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.deposit_for(amount, ctx.remaining_accounts)
+    }
+
+    pub fn deposit_sol<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositSol<'info>>,
+        amount: u64,
+        as_collateral: bool,
+    ) -> Result<()> {
+        ctx.accounts
+            .deposit_sol(amount, as_collateral, ctx.remaining_accounts)
+    }
+
+    pub fn withdraw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Withdraw<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.withdraw(amount, ctx.remaining_accounts)
+    }
+
+    pub fn withdraw_sol<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawSol<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.withdraw_sol(amount, ctx.remaining_accounts)
+    }
+
+    pub fn deposit_receipt_as_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositReceiptAsCollateral<'info>>,
+        shares: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .deposit_receipt_as_collateral(shares, ctx.remaining_accounts)
+    }
+
+    pub fn withdraw_receipt_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawReceiptCollateral<'info>>,
+        shares: u64,
     ) -> Result<()> {
         ctx.accounts
-            .init_bank(liquidation_threshold, max_ltv, &ctx.bumps)
+            .withdraw_receipt_collateral(shares, ctx.remaining_accounts)
     }
 
-    pub fn init_user(ctx: Context<InitUser>, mint_usdc: Pubkey) -> Result<()> {
-        ctx.accounts.init_user(mint_usdc, &ctx.bumps)
+    pub fn redeem_receipt<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RedeemReceipt<'info>>,
+        shares: u64,
+    ) -> Result<()> {
+        ctx.accounts.redeem_receipt(shares, ctx.remaining_accounts)
+    }
+
+    pub fn borrow<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Borrow<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.borrow(amount, ctx.remaining_accounts)
+    }
+
+    pub fn borrow_fixed<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BorrowFixed<'info>>,
+        amount: u64,
+        term: i64,
+        max_rate: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .borrow_fixed(amount, term, max_rate, ctx.remaining_accounts, &ctx.bumps)
+    }
+
+    pub fn settle_fixed_borrow<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleFixedBorrow<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.settle_fixed_borrow(ctx.remaining_accounts)
+    }
+
+    pub fn borrow_sol<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BorrowSol<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.borrow_sol(amount, ctx.remaining_accounts)
+    }
+
+    pub fn leverage<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Leverage<'info>>,
+        amount: u64,
+        min_collateral_out: u64,
+        swap_data: Vec<u8>,
+        borrow_hook_accounts: u8,
+        deposit_hook_accounts: u8,
+    ) -> Result<()> {
+        ctx.accounts.leverage(
+            amount,
+            min_collateral_out,
+            swap_data,
+            borrow_hook_accounts,
+            deposit_hook_accounts,
+            ctx.remaining_accounts,
+        )
+    }
+
+    pub fn swap_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapCollateral<'info>>,
+        amount: u64,
+        min_amount_out: u64,
+        swap_data: Vec<u8>,
+        withdraw_hook_accounts: u8,
+        deposit_hook_accounts: u8,
+    ) -> Result<()> {
+        ctx.accounts.swap_collateral(
+            amount,
+            min_amount_out,
+            swap_data,
+            withdraw_hook_accounts,
+            deposit_hook_accounts,
+            ctx.remaining_accounts,
+        )
+    }
+
+    pub fn repay_with_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayWithCollateral<'info>>,
+        amount: u64,
+        min_amount_out: u64,
+        swap_data: Vec<u8>,
+        withdraw_hook_accounts: u8,
+        repay_hook_accounts: u8,
+    ) -> Result<()> {
+        ctx.accounts.repay_with_collateral(
+            amount,
+            min_amount_out,
+            swap_data,
+            withdraw_hook_accounts,
+            repay_hook_accounts,
+            ctx.remaining_accounts,
+        )
+    }
+
+    pub fn replay<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Repay<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.repay(amount, ctx.remaining_accounts)
+    }
+
+    pub fn repay_obligation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayObligation<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .repay_obligation(amount, ctx.remaining_accounts)
+    }
+
+    pub fn repay_sol<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepaySol<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.repay_sol(amount, ctx.remaining_accounts)
     }
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-        ctx.accounts.deposit(amount)
+    pub fn liquidate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.liquidate(amount, ctx.remaining_accounts)
+    }
+
+    pub fn socialize_loss<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SocializeLoss<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.socialize_loss(ctx.remaining_accounts)
     }
 
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-        ctx.accounts.withdraw(amount)
+    pub fn sweep_reserves<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepReserves<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.sweep_reserves(ctx.remaining_accounts)
+    }
+
+    pub fn request_insurance_withdrawal(
+        ctx: Context<RequestInsuranceWithdrawal>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.request_insurance_withdrawal(amount)
+    }
+
+    pub fn withdraw_insurance<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawInsurance<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.withdraw_insurance(ctx.remaining_accounts)
     }
 
-    pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
-        ctx.accounts.borrow(amount)
+    pub fn init_referrer(ctx: Context<InitReferrer>) -> Result<()> {
+        ctx.accounts.init_referrer(&ctx.bumps)
     }
 
-    pub fn replay(ctx: Context<Repay>, amount: u64) -> Result<()> {
-        ctx.accounts.repay(amount)
+    pub fn claim_referrer_fees<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimReferrerFees<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.claim_referrer_fees(ctx.remaining_accounts)
+    }
+
+    pub fn init_reward_pool(
+        ctx: Context<InitRewardPool>,
+        side: RewardSide,
+        allowed_hook_programs: Vec<Pubkey>,
+    ) -> Result<()> {
+        ctx.accounts
+            .init_reward_pool(side, allowed_hook_programs, &ctx.bumps)
+    }
+
+    pub fn fund_reward_pool<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FundRewardPool<'info>>,
+        amount: u64,
+        emission_per_second: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .fund_reward_pool(amount, emission_per_second, ctx.remaining_accounts)
+    }
+
+    pub fn init_user_reward(ctx: Context<InitUserReward>) -> Result<()> {
+        ctx.accounts.init_user_reward(&ctx.bumps)
+    }
+
+    pub fn claim_rewards<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimRewards<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.claim_rewards(ctx.remaining_accounts)
     }
 }
@@ -1,20 +1,560 @@
 use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::{Price, PriceUpdateV2};
+
+use crate::{
+    apply_bps, error::ErrorCode, User, BPS_DENOMINATOR, MAX_AGE, MAX_REWARD_POOLS,
+    MAX_TRANSFER_HOOK_PROGRAMS, SECONDS_PER_YEAR, VALUE_DECIMALS,
+};
 
 #[account]
 #[derive(InitSpace)]
 pub struct Bank {
     pub authority: Pubkey,
     pub mint: Pubkey,
+    pub mint_decimals: u8,
+    pub collateral_mint: Pubkey,
+    /// Pyth feed pricing `mint`, set by the market admin. Unset until then,
+    /// which keeps the bank out of every valuation.
+    pub oracle_feed_id: [u8; 32],
     pub total_deposit: u64,
     pub total_borrowed: u64,
     pub total_deposit_shares: u64,
+    /// Deposit shares locked as user collateral rather than held as wallet
+    /// receipts. Supply-side reward pools emit across these alone.
+    pub collateral_shares: u64,
     pub total_borrowed_shares: u64,
     pub liquidation_threshold: u64,
+    /// Bonus, in bps, paid on liquidations of barely unhealthy positions.
     pub liquidation_bonus: u64,
+    /// Bonus, in bps, once the health factor has fallen `liquidation_bonus_ramp`
+    /// bps below 1.0. In between it scales linearly.
+    pub max_liquidation_bonus: u64,
+    pub liquidation_bonus_ramp: u64,
     pub liquidation_close_factor: u64,
+    /// Share of the liquidation bonus, in bps, paid to the fee vault instead
+    /// of the liquidator.
+    pub liquidation_fee: u64,
     pub max_ltv: u64,
     pub last_updated: i64,
+    pub last_updated_slot: u64,
     pub interest_rate: u64,
+    /// Premium, in bps per year of term, added to `interest_rate` when
+    /// quoting a fixed rate.
+    pub fixed_rate_premium: u64,
+    /// Longest fixed-rate term in seconds. Zero disables fixed-rate borrowing.
+    pub max_fixed_term: i64,
+    pub risk_tier: RiskTier,
+    pub debt_ceiling: u64,
+    pub isolated_debt: u64,
+    pub borrowable_in_isolation: bool,
+    pub emode_category: u8,
+    pub transfer_hook_programs: [Pubkey; MAX_TRANSFER_HOOK_PROGRAMS],
+    /// Reward pools emitting to this bank, filled in creation order. Every
+    /// change to its shares must accrue all of them.
+    pub reward_pools: [Pubkey; MAX_REWARD_POOLS],
+    /// Share, in bps, of interest and liquidation fees set aside for the
+    /// insurance vault.
+    pub insurance_fee: u64,
+    /// Interest set aside for insurance that still sits in the treasury.
+    pub insurance_reserve: u64,
+    pub insurance_withdrawal_amount: u64,
+    pub insurance_withdrawal_unlocks_at: i64,
+    /// Origination fee, in bps of the amount borrowed, added to the debt.
+    pub borrow_fee_bps: u64,
+    /// Share, in bps, of the borrow fee paid to the referrer of the borrow.
+    pub host_fee_bps: u64,
+    /// Borrow fees owed to the fee vault that still sit in the treasury.
+    pub protocol_fees: u64,
+    /// Borrow fees credited to `Referrer` accounts and not yet claimed.
+    pub referrer_fees: u64,
+    /// Program `leverage` and collateral swaps may route this bank's tokens through.
+    pub swap_program: Pubkey,
     pub treasury_bump: u8,
+    pub collateral_mint_bump: u8,
+    pub collateral_vault_bump: u8,
+    pub fee_vault_bump: u8,
+    pub insurance_vault_bump: u8,
     pub bump: u8,
 }
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RiskTier {
+    Standard,
+    /// Collateral may only back borrows of `borrowable_in_isolation` banks,
+    /// up to `debt_ceiling` across all users.
+    Isolated,
+    /// May not be borrowed alongside any other debt in the same `User`.
+    IsolatedBorrow,
+}
+
+impl Bank {
+    /// Grows both sides of the book by the simple interest owed since the last
+    /// update, which raises the value of every outstanding deposit and borrow share.
+    /// The `insurance_fee` slice goes to `insurance_reserve` instead of depositors.
+    pub fn accrue_interest(&mut self, clock: &Clock) -> Result<()> {
+        if self.last_updated_slot == clock.slot {
+            return Ok(());
+        }
+
+        let elapsed = clock
+            .unix_timestamp
+            .saturating_sub(self.last_updated)
+            .max(0);
+        if self.total_borrowed != 0 && elapsed != 0 {
+            let interest = simple_interest(self.total_borrowed, self.interest_rate, elapsed)?;
+
+            self.total_borrowed = self
+                .total_borrowed
+                .checked_add(interest)
+                .ok_or(ErrorCode::MathOverflow)?;
+            self.book_interest(interest)?;
+        }
+
+        self.last_updated = clock.unix_timestamp;
+        self.last_updated_slot = clock.slot;
+
+        Ok(())
+    }
+
+    /// Splits `interest` added to the debt between depositors and, by
+    /// `insurance_fee`, the insurance reserve.
+    pub fn book_interest(&mut self, interest: u64) -> Result<()> {
+        let insurance = apply_bps(interest, self.insurance_fee)?;
+        self.total_deposit = self
+            .total_deposit
+            .checked_add(interest - insurance)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.insurance_reserve = self
+            .insurance_reserve
+            .checked_add(insurance)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Takes back `interest` removed from the debt in the same split
+    /// `book_interest` credited it.
+    pub fn reverse_interest(&mut self, interest: u64) -> Result<()> {
+        let insurance = apply_bps(interest, self.insurance_fee)?;
+        self.total_deposit = self.total_deposit.saturating_sub(interest - insurance);
+        self.insurance_reserve = self.insurance_reserve.saturating_sub(insurance);
+
+        Ok(())
+    }
+
+    pub fn reward_pools(&self) -> impl Iterator<Item = &Pubkey> {
+        self.reward_pools
+            .iter()
+            .take_while(|pool| **pool != Pubkey::default())
+    }
+
+    /// Annual rate, in bps, locked by a fixed-rate borrow of `term` seconds.
+    pub fn fixed_rate_for(&self, term: i64) -> Result<u64> {
+        if term <= 0 || term > self.max_fixed_term {
+            return Err(ErrorCode::InvalidFixedTerm.into());
+        }
+
+        let premium = (self.fixed_rate_premium as u128)
+            .checked_mul(term as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / SECONDS_PER_YEAR as u128;
+
+        u64::try_from(premium)
+            .ok()
+            .and_then(|premium| self.interest_rate.checked_add(premium))
+            .ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    /// `mint`'s price from `price_update`, which must carry this bank's feed.
+    pub fn price(&self, price_update: &PriceUpdateV2, clock: &Clock) -> Result<Price> {
+        if self.oracle_feed_id == [0; 32] {
+            return Err(ErrorCode::OracleNotSet.into());
+        }
+
+        Ok(price_update.get_price_no_older_than(clock, MAX_AGE, &self.oracle_feed_id)?)
+    }
+
+    pub fn deposit_shares_for(&self, amount: u64, round_up: bool) -> Result<u64> {
+        convert(
+            amount,
+            self.total_deposit_shares,
+            self.total_deposit,
+            round_up,
+        )
+    }
+
+    pub fn deposit_amount_for(&self, shares: u64) -> Result<u64> {
+        convert(shares, self.total_deposit, self.total_deposit_shares, false)
+    }
+
+    pub fn borrow_shares_for(&self, amount: u64, round_up: bool) -> Result<u64> {
+        convert(
+            amount,
+            self.total_borrowed_shares,
+            self.total_borrowed,
+            round_up,
+        )
+    }
+
+    pub fn borrow_amount_for(&self, shares: u64) -> Result<u64> {
+        convert(
+            shares,
+            self.total_borrowed,
+            self.total_borrowed_shares,
+            true,
+        )
+    }
+
+    /// Books `amount` of tokens that arrived in the treasury as a deposit and
+    /// returns the receipt shares to mint for it.
+    pub fn credit_receipts(&mut self, amount: u64) -> Result<u64> {
+        let shares = self.deposit_shares_for(amount, false)?;
+
+        self.total_deposit = self
+            .total_deposit
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.total_deposit_shares = self
+            .total_deposit_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(shares)
+    }
+
+    /// Books the burn of `shares` receipt tokens and returns the tokens they
+    /// redeem for.
+    pub fn redeem_receipts(&mut self, shares: u64) -> Result<u64> {
+        let amount = self.deposit_amount_for(shares)?;
+
+        self.total_deposit = self.total_deposit.saturating_sub(amount);
+        self.total_deposit_shares = self
+            .total_deposit_shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(amount)
+    }
+
+    /// Books `amount` of tokens that arrived in the treasury as `user`'s
+    /// collateral and returns the shares credited for it.
+    pub fn credit_deposit(&mut self, user: &mut User, amount: u64) -> Result<u64> {
+        let shares = self.credit_receipts(amount)?;
+        self.lock_receipts(user, shares, amount)?;
+
+        Ok(shares)
+    }
+
+    /// Credits `shares` receipts worth `amount`, now held in the collateral
+    /// vault, to `user`'s deposit.
+    pub fn lock_receipts(&mut self, user: &mut User, shares: u64, amount: u64) -> Result<()> {
+        let (deposited, deposited_shares) = user.deposit_mut(&self.mint)?;
+        *deposited = deposited.saturating_add(amount);
+        *deposited_shares = deposited_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        self.collateral_shares = self
+            .collateral_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Takes `shares` receipts worth `amount` out of `user`'s deposit as they
+    /// leave the collateral vault.
+    pub fn unlock_receipts(&mut self, user: &mut User, shares: u64, amount: u64) -> Result<()> {
+        let (deposited, deposited_shares) = user.deposit_mut(&self.mint)?;
+        *deposited_shares = deposited_shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::InsufficientFunds)?;
+        // The nominal amount goes with the last share, dust and all.
+        *deposited = if *deposited_shares == 0 {
+            0
+        } else {
+            deposited.saturating_sub(amount)
+        };
+
+        self.collateral_shares = self
+            .collateral_shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Takes `amount` out of `user`'s deposit and returns the shares removed,
+    /// rounded against the user unless the whole deposit goes.
+    pub fn debit_deposit(&mut self, user: &mut User, amount: u64) -> Result<u64> {
+        let deposited_shares = user.deposited_shares(&self.mint);
+        let user_value = self.deposit_amount_for(deposited_shares)?;
+        if amount > user_value {
+            return Err(ErrorCode::InsufficientFunds.into());
+        }
+
+        let shares = if amount == user_value {
+            deposited_shares
+        } else {
+            self.deposit_shares_for(amount, true)?.min(deposited_shares)
+        };
+
+        self.unlock_receipts(user, shares, amount)?;
+
+        self.total_deposit = self
+            .total_deposit
+            .checked_sub(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.total_deposit_shares = self
+            .total_deposit_shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(shares)
+    }
+
+    /// Books `amount` as new debt of `user` and returns the shares it took,
+    /// rounded against the user.
+    pub fn credit_borrow(&mut self, user: &mut User, amount: u64) -> Result<u64> {
+        let shares = self.borrow_shares_for(amount, true)?;
+
+        let (borrowed, borrowed_shares) = user.borrow_mut(&self.mint)?;
+        *borrowed = borrowed.saturating_add(amount);
+        *borrowed_shares = borrowed_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        self.total_borrowed = self
+            .total_borrowed
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.total_borrowed_shares = self
+            .total_borrowed_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(shares)
+    }
+
+    /// Books `amount` repaid against `user`'s debt, which must cover it, and
+    /// returns the shares cleared. Paying the full debt clears every share.
+    pub fn debit_borrow(&mut self, user: &mut User, amount: u64) -> Result<u64> {
+        let borrowed_shares = user.borrowed_shares(&self.mint);
+        let debt = self.borrow_amount_for(borrowed_shares)?;
+        if amount > debt {
+            return Err(ErrorCode::OverRepay.into());
+        }
+
+        let shares = if amount == debt {
+            borrowed_shares
+        } else {
+            self.borrow_shares_for(amount, false)?.min(borrowed_shares)
+        };
+
+        let (borrowed, borrowed_shares) = user.borrow_mut(&self.mint)?;
+        *borrowed_shares -= shares;
+        *borrowed = if *borrowed_shares == 0 {
+            0
+        } else {
+            borrowed.saturating_sub(amount)
+        };
+
+        self.total_borrowed = self.total_borrowed.saturating_sub(amount);
+        self.total_borrowed_shares = self
+            .total_borrowed_shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(shares)
+    }
+
+    /// Origination fee added to the debt when `amount` is borrowed.
+    pub fn borrow_fee_for(&self, amount: u64) -> Result<u64> {
+        apply_bps(amount, self.borrow_fee_bps)
+    }
+
+    /// Debt counted against an isolated bank's ceiling, normalized to `VALUE_DECIMALS`
+    /// on the assumption that only dollar stables are borrowable in isolation.
+    pub fn isolated_debt_for(&self, amount: u64) -> Result<u64> {
+        let scale = VALUE_DECIMALS as i32 - self.mint_decimals as i32;
+        let factor = 10u128
+            .checked_pow(scale.unsigned_abs())
+            .ok_or(ErrorCode::MathOverflow)?;
+        let value = if scale >= 0 {
+            (amount as u128)
+                .checked_mul(factor)
+                .ok_or(ErrorCode::MathOverflow)?
+        } else {
+            amount as u128 / factor
+        };
+
+        u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    pub fn add_isolated_debt(&mut self, debt: u64) -> Result<()> {
+        let isolated_debt = self
+            .isolated_debt
+            .checked_add(debt)
+            .ok_or(ErrorCode::MathOverflow)?;
+        if isolated_debt > self.debt_ceiling {
+            return Err(ErrorCode::DebtCeilingExceeded.into());
+        }
+
+        self.isolated_debt = isolated_debt;
+        Ok(())
+    }
+
+    pub fn release_isolated_debt(&mut self, debt: u64) {
+        self.isolated_debt = self.isolated_debt.saturating_sub(debt);
+    }
+
+    /// USD value of `amount` base units of this bank's mint, with `VALUE_DECIMALS` decimals.
+    pub fn market_value(&self, amount: u64, price: &Price) -> Result<u64> {
+        if price.price <= 0 {
+            return Err(ErrorCode::InvalidPrice.into());
+        }
+
+        let scale = VALUE_DECIMALS as i32 - self.mint_decimals as i32 + price.exponent;
+        let value = (amount as u128)
+            .checked_mul(price.price as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let factor = 10u128
+            .checked_pow(scale.unsigned_abs())
+            .ok_or(ErrorCode::MathOverflow)?;
+        let value = if scale >= 0 {
+            value.checked_mul(factor).ok_or(ErrorCode::MathOverflow)?
+        } else {
+            value / factor
+        };
+
+        u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    /// Liquidation bonus for a position with the given cached values, rising
+    /// from `liquidation_bonus` to `max_liquidation_bonus` as health worsens.
+    pub fn liquidation_bonus_for(
+        &self,
+        borrowed_value: u64,
+        unhealthy_borrow_value: u64,
+    ) -> Result<u64> {
+        if borrowed_value == 0 || self.liquidation_bonus_ramp == 0 {
+            return Ok(self.max_liquidation_bonus);
+        }
+
+        // How far below 1.0 the health factor is, in bps.
+        let shortfall = (borrowed_value.saturating_sub(unhealthy_borrow_value) as u128)
+            .checked_mul(BPS_DENOMINATOR as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / borrowed_value as u128;
+        let shortfall = shortfall.min(self.liquidation_bonus_ramp as u128);
+
+        let range = self
+            .max_liquidation_bonus
+            .saturating_sub(self.liquidation_bonus) as u128;
+        let bonus = range * shortfall / self.liquidation_bonus_ramp as u128;
+
+        Ok(self.liquidation_bonus + bonus as u64)
+    }
+
+    /// Base units of this bank's mint worth `value` (with `VALUE_DECIMALS`
+    /// decimals), rounded down. The inverse of `market_value`.
+    pub fn amount_for_value(&self, value: u64, price: &Price) -> Result<u64> {
+        if price.price <= 0 {
+            return Err(ErrorCode::InvalidPrice.into());
+        }
+
+        let scale = VALUE_DECIMALS as i32 - self.mint_decimals as i32 + price.exponent;
+        let factor = 10u128
+            .checked_pow(scale.unsigned_abs())
+            .ok_or(ErrorCode::MathOverflow)?;
+        let amount = if scale >= 0 {
+            let divisor = (price.price as u128)
+                .checked_mul(factor)
+                .ok_or(ErrorCode::MathOverflow)?;
+            value as u128 / divisor
+        } else {
+            (value as u128)
+                .checked_mul(factor)
+                .ok_or(ErrorCode::MathOverflow)?
+                / price.price as u128
+        };
+
+        u64::try_from(amount).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+}
+
+/// Interest on `amount` at `rate` bps a year over `elapsed` seconds.
+pub fn simple_interest(amount: u64, rate: u64, elapsed: i64) -> Result<u64> {
+    let interest = (amount as u128)
+        .checked_mul(rate as u128)
+        .and_then(|value| value.checked_mul(elapsed.max(0) as u128))
+        .ok_or(ErrorCode::MathOverflow)?
+        / (BPS_DENOMINATOR as u128 * SECONDS_PER_YEAR as u128);
+
+    u64::try_from(interest).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// `value * numerator / denominator`, treating an empty pool as 1:1.
+fn convert(value: u64, numerator: u64, denominator: u64, round_up: bool) -> Result<u64> {
+    if numerator == 0 || denominator == 0 {
+        return Ok(value);
+    }
+
+    let product = (value as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let mut result = product / denominator as u128;
+    if round_up && product % denominator as u128 != 0 {
+        result += 1;
+    }
+
+    u64::try_from(result).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank() -> Bank {
+        let data = vec![0u8; 8 + Bank::INIT_SPACE];
+        Bank::try_deserialize_unchecked(&mut data.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn later_deposits_do_not_dilute_earlier_lenders() {
+        let mut bank = bank();
+        bank.interest_rate = 1_000;
+        let first = bank.credit_receipts(1_000_000).unwrap();
+        bank.total_borrowed = 500_000;
+        bank.total_borrowed_shares = 500_000;
+
+        let clock = Clock {
+            slot: 1,
+            unix_timestamp: SECONDS_PER_YEAR as i64,
+            ..Clock::default()
+        };
+        bank.accrue_interest(&clock).unwrap();
+        assert_eq!(bank.deposit_amount_for(first).unwrap(), 1_050_000);
+
+        let second = bank.credit_receipts(1_050_000).unwrap();
+        assert_eq!(second, 1_000_000);
+        assert_eq!(bank.deposit_amount_for(first).unwrap(), 1_050_000);
+        assert_eq!(bank.deposit_amount_for(second).unwrap(), 1_050_000);
+    }
+
+    #[test]
+    fn liquidation_bonus_ramps_with_the_shortfall() {
+        let mut bank = bank();
+        bank.liquidation_bonus = 500;
+        bank.max_liquidation_bonus = 1_500;
+        bank.liquidation_bonus_ramp = 1_000;
+
+        assert_eq!(bank.liquidation_bonus_for(100, 100).unwrap(), 500);
+        // 5% below a health factor of 1.0, halfway up the 10% ramp.
+        assert_eq!(bank.liquidation_bonus_for(100, 95).unwrap(), 1_000);
+        assert_eq!(bank.liquidation_bonus_for(100, 50).unwrap(), 1_500);
+
+        bank.liquidation_bonus_ramp = 0;
+        assert_eq!(bank.liquidation_bonus_for(100, 95).unwrap(), 1_500);
+    }
+}
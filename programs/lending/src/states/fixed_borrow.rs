@@ -0,0 +1,129 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, simple_interest, Bank, User};
+
+/// The part of a user's debt in one bank borrowed at a locked rate. The debt
+/// itself stays in the user's borrow shares; settling re-prices those shares
+/// to what the fixed rate owes, with depositors and the insurance reserve
+/// taking the difference.
+#[account]
+#[derive(InitSpace)]
+pub struct FixedBorrow {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    /// Borrow shares that accrue at `rate`. Zero once repaid or matured.
+    pub shares: u64,
+    /// What the shares owed at `last_settled`.
+    pub amount: u64,
+    /// Annual rate in bps.
+    pub rate: u64,
+    pub maturity: i64,
+    pub last_settled: i64,
+    pub bump: u8,
+}
+
+impl FixedBorrow {
+    /// Reads the position at `info`, the derived FixedBorrow address for a
+    /// user and mint, or `None` if none was ever opened there.
+    pub fn load(info: &AccountInfo) -> Result<Option<FixedBorrow>> {
+        if info.data_is_empty() {
+            return Ok(None);
+        }
+        if info.owner != &crate::ID {
+            return Err(ErrorCode::InvalidFixedBorrow.into());
+        }
+
+        FixedBorrow::try_deserialize(&mut &info.try_borrow_data()?[..]).map(Some)
+    }
+
+    /// Settles the position at `info`, if any, and writes it back.
+    pub fn settle_at(info: &AccountInfo, bank: &mut Bank, user: &mut User, now: i64) -> Result<()> {
+        if let Some(mut fixed_borrow) = Self::load(info)? {
+            fixed_borrow.settle(bank, user, now)?;
+            fixed_borrow.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.shares != 0
+    }
+
+    /// Brings the position up to `now` against a bank already accrued to
+    /// `now`. Past maturity the debt accrues at the bank's variable rate and
+    /// the position ends, leaving its shares as ordinary variable debt.
+    pub fn settle(&mut self, bank: &mut Bank, user: &mut User, now: i64) -> Result<()> {
+        if !self.is_active() || now <= self.last_settled {
+            return Ok(());
+        }
+
+        // Repayments and liquidations since the last settlement came out of
+        // the fixed shares only once the variable ones were gone.
+        let user_shares = user.borrowed_shares(&self.mint);
+        if user_shares < self.shares {
+            self.amount = (self.amount as u128 * user_shares as u128 / self.shares as u128) as u64;
+            self.shares = user_shares;
+        }
+        if !self.is_active() {
+            return Ok(());
+        }
+
+        let fixed_until = now.min(self.maturity);
+        let mut debt = self
+            .amount
+            .checked_add(simple_interest(
+                self.amount,
+                self.rate,
+                fixed_until - self.last_settled,
+            )?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        if now > self.maturity {
+            let variable_since = self.maturity.max(self.last_settled);
+            debt = debt
+                .checked_add(simple_interest(
+                    debt,
+                    bank.interest_rate,
+                    now - variable_since,
+                )?)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        // Swap the shares out at their variable value and back in at `debt`,
+        // leaving the price of every other borrow share unchanged.
+        let value = bank.borrow_amount_for(self.shares)?;
+        bank.total_borrowed = bank.total_borrowed.saturating_sub(value);
+        bank.total_borrowed_shares -= self.shares;
+
+        let shares = bank.borrow_shares_for(debt, true)?;
+        bank.total_borrowed = bank
+            .total_borrowed
+            .checked_add(debt)
+            .ok_or(ErrorCode::MathOverflow)?;
+        bank.total_borrowed_shares = bank
+            .total_borrowed_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        if debt >= value {
+            bank.book_interest(debt - value)?;
+        } else {
+            bank.reverse_interest(value - debt)?;
+        }
+
+        let (borrowed, borrowed_shares) = user.borrow_mut(&self.mint)?;
+        *borrowed_shares = *borrowed_shares - self.shares + shares;
+        *borrowed = borrowed.saturating_add(debt).saturating_sub(value);
+
+        self.shares = shares;
+        self.amount = debt;
+        self.last_settled = now;
+
+        if now >= self.maturity {
+            self.shares = 0;
+            self.amount = 0;
+        }
+
+        Ok(())
+    }
+}
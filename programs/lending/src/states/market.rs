@@ -0,0 +1,10 @@
+use anchor_lang::prelude::*;
+
+/// Program-wide singleton. Its admin approves each bank's oracle feed and
+/// creates e-mode categories, which permissionless bank listing cannot.
+#[account]
+#[derive(InitSpace)]
+pub struct Market {
+    pub admin: Pubkey,
+    pub bump: u8,
+}
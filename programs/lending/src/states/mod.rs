@@ -1,5 +1,20 @@
 pub mod user;
 pub use user::*;
 
+pub mod market;
+pub use market::*;
+
 pub mod bank;
 pub use bank::*;
+
+pub mod emode;
+pub use emode::*;
+
+pub mod referrer;
+pub use referrer::*;
+
+pub mod reward;
+pub use reward::*;
+
+pub mod fixed_borrow;
+pub use fixed_borrow::*;
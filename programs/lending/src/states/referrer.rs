@@ -0,0 +1,12 @@
+use anchor_lang::prelude::*;
+
+/// Host fees earned by `owner` on one bank, claimable from its treasury.
+#[account]
+#[derive(InitSpace)]
+pub struct Referrer {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub claimable: u64,
+    pub total_earned: u64,
+    pub bump: u8,
+}
@@ -0,0 +1,113 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, Bank, User, MAX_TRANSFER_HOOK_PROGRAMS, REWARD_INDEX_SCALE};
+
+/// Emits `reward_mint` from its vault to one side of a bank, pro rata to
+/// shares. On the supply side only collateral shares earn, since wallet
+/// receipts have no `User` to pay. Emissions owed to shares without a
+/// `UserReward` stay in the vault.
+#[account]
+#[derive(InitSpace)]
+pub struct RewardPool {
+    pub mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub side: RewardSide,
+    pub emission_per_second: u64,
+    /// Rewards emitted per share so far, scaled by `REWARD_INDEX_SCALE`.
+    pub reward_index: u128,
+    /// Funded rewards not yet emitted.
+    pub undistributed: u64,
+    pub last_updated: i64,
+    /// Transfer hook programs `reward_mint` may use, separate from the bank's.
+    pub transfer_hook_programs: [Pubkey; MAX_TRANSFER_HOOK_PROGRAMS],
+    pub vault_bump: u8,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RewardSide {
+    Supply,
+    Borrow,
+}
+
+/// A user's position in one `RewardPool`.
+#[account]
+#[derive(InitSpace)]
+pub struct UserReward {
+    pub user: Pubkey,
+    pub pool: Pubkey,
+    /// Pool index the user has been paid up to.
+    pub reward_index: u128,
+    /// Shares the user held when last settled.
+    pub shares: u64,
+    pub accrued: u64,
+    pub bump: u8,
+}
+
+impl RewardPool {
+    pub fn total_shares(&self, bank: &Bank) -> u64 {
+        match self.side {
+            RewardSide::Supply => bank.collateral_shares,
+            RewardSide::Borrow => bank.total_borrowed_shares,
+        }
+    }
+
+    pub fn user_shares(&self, user: &User) -> u64 {
+        match self.side {
+            RewardSide::Supply => user.deposited_shares(&self.mint),
+            RewardSide::Borrow => user.borrowed_shares(&self.mint),
+        }
+    }
+
+    /// Emits rewards up to `now` across `total_shares`. Nothing is emitted
+    /// while the side is empty.
+    pub fn accrue(&mut self, total_shares: u64, now: i64) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_updated);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+        self.last_updated = now;
+
+        if total_shares == 0 {
+            return Ok(());
+        }
+
+        let emitted = self
+            .emission_per_second
+            .saturating_mul(elapsed as u64)
+            .min(self.undistributed);
+        let index_delta = (emitted as u128)
+            .checked_mul(REWARD_INDEX_SCALE)
+            .ok_or(ErrorCode::MathOverflow)?
+            / total_shares as u128;
+
+        self.reward_index = self
+            .reward_index
+            .checked_add(index_delta)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.undistributed -= emitted;
+
+        Ok(())
+    }
+}
+
+impl UserReward {
+    /// Pays the user up to the pool's index. Only shares held since the last
+    /// settlement earn, so a position change that skipped this pool can
+    /// neither backdate new shares nor keep earning on removed ones.
+    pub fn accrue(&mut self, pool: &RewardPool, shares: u64) -> Result<()> {
+        let shares = self.shares.min(shares) as u128;
+        let earned = shares
+            .checked_mul(pool.reward_index - self.reward_index)
+            .ok_or(ErrorCode::MathOverflow)?
+            / REWARD_INDEX_SCALE;
+
+        self.accrued = self
+            .accrued
+            .checked_add(u64::try_from(earned).map_err(|_| ErrorCode::MathOverflow)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.reward_index = pool.reward_index;
+
+        Ok(())
+    }
+}
@@ -1,9 +1,13 @@
 use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::Price;
+
+use crate::{error::ErrorCode, Bank, EmodeCategory, BPS_DENOMINATOR};
 
 #[account]
 #[derive(InitSpace)]
 pub struct User {
     pub owner: Pubkey,
+    pub sub_account: u16,
     pub deposited_sol: u64,
     pub deposited_sol_shares: u64,
     pub borrowed_sol: u64,
@@ -13,7 +17,225 @@ pub struct User {
     pub borrowed_usdc: u64,
     pub borrowed_usdc_shares: u64,
     pub mint_usdc: Pubkey,
+    /// Mint of the non-USDC side. Banks for any other mint are rejected.
+    pub mint_sol: Pubkey,
     pub last_updated: i64,
     pub last_updated_borrow: i64,
+    pub deposited_value: u64,
+    pub borrowed_value: u64,
+    pub allowed_borrow_value: u64,
+    pub unhealthy_borrow_value: u64,
+    pub last_refreshed_slot: u64,
+    pub emode_category: u8,
+    pub delegate: Pubkey,
+    pub delegate_permissions: u8,
+    pub accepts_third_party_deposits: bool,
+    pub accepts_third_party_repays: bool,
+    /// Mint of the isolated bank whose debt ceiling holds `isolated_debt`.
+    pub isolated_collateral: Pubkey,
+    /// This user's share of that bank's `isolated_debt`, released as it is repaid.
+    pub isolated_debt: u64,
+    /// Owner of the `Referrer` accounts credited with this user's host fees.
+    pub referrer: Pubkey,
     pub bump: u8,
 }
+
+impl User {
+    /// Attributes the position to `referrer` the first time one is given and
+    /// rejects any different referrer afterwards.
+    pub fn attribute_referrer(&mut self, referrer: &Pubkey) -> Result<()> {
+        if *referrer == self.owner {
+            return Err(ErrorCode::SelfReferral.into());
+        }
+
+        if self.referrer == Pubkey::default() {
+            self.referrer = *referrer;
+        } else if self.referrer != *referrer {
+            return Err(ErrorCode::ReferrerMismatch.into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether `a` and `b` are the position's USDC and SOL mints, in either order.
+    pub fn is_pair(&self, a: &Pubkey, b: &Pubkey) -> bool {
+        (*a == self.mint_usdc && *b == self.mint_sol)
+            || (*a == self.mint_sol && *b == self.mint_usdc)
+    }
+
+    /// Deposited shares in `mint`'s bank, zero for a mint the position cannot hold.
+    pub fn deposited_shares(&self, mint: &Pubkey) -> u64 {
+        if *mint == self.mint_usdc {
+            self.deposited_usdc_shares
+        } else if *mint == self.mint_sol {
+            self.deposited_sol_shares
+        } else {
+            0
+        }
+    }
+
+    /// Borrowed shares in `mint`'s bank, zero for a mint the position cannot hold.
+    pub fn borrowed_shares(&self, mint: &Pubkey) -> u64 {
+        if *mint == self.mint_usdc {
+            self.borrowed_usdc_shares
+        } else if *mint == self.mint_sol {
+            self.borrowed_sol_shares
+        } else {
+            0
+        }
+    }
+
+    /// `(deposited, deposited_shares)` for `mint`'s side of the position.
+    pub fn deposit_mut(&mut self, mint: &Pubkey) -> Result<(&mut u64, &mut u64)> {
+        if *mint == self.mint_usdc {
+            Ok((&mut self.deposited_usdc, &mut self.deposited_usdc_shares))
+        } else if *mint == self.mint_sol {
+            Ok((&mut self.deposited_sol, &mut self.deposited_sol_shares))
+        } else {
+            Err(ErrorCode::UnsupportedMint.into())
+        }
+    }
+
+    /// `(borrowed, borrowed_shares)` for `mint`'s side of the position.
+    pub fn borrow_mut(&mut self, mint: &Pubkey) -> Result<(&mut u64, &mut u64)> {
+        if *mint == self.mint_usdc {
+            Ok((&mut self.borrowed_usdc, &mut self.borrowed_usdc_shares))
+        } else if *mint == self.mint_sol {
+            Ok((&mut self.borrowed_sol, &mut self.borrowed_sol_shares))
+        } else {
+            Err(ErrorCode::UnsupportedMint.into())
+        }
+    }
+
+    /// Recomputes the cached valuation from every bank the position touches.
+    /// Banks must already be accrued to the current time. The e-mode category's
+    /// parameters replace each bank's only when every position belongs to it.
+    pub fn refresh(
+        &mut self,
+        positions: &[(Bank, Price)],
+        emode: Option<&EmodeCategory>,
+        slot: u64,
+    ) -> Result<()> {
+        let emode = emode.filter(|emode| {
+            emode.id == self.emode_category
+                && positions
+                    .iter()
+                    .all(|(bank, _)| bank.emode_category == emode.id)
+        });
+
+        let mut deposited_value: u64 = 0;
+        let mut borrowed_value: u64 = 0;
+        let mut allowed_borrow_value: u64 = 0;
+        let mut unhealthy_borrow_value: u64 = 0;
+        let mut seen_usdc = false;
+        let mut seen_sol = false;
+
+        for (bank, price) in positions {
+            let seen = if bank.mint == self.mint_usdc {
+                &mut seen_usdc
+            } else if bank.mint == self.mint_sol {
+                &mut seen_sol
+            } else {
+                return Err(ErrorCode::UnsupportedMint.into());
+            };
+            if *seen {
+                return Err(ErrorCode::InvalidRefreshAccounts.into());
+            }
+            *seen = true;
+
+            let deposited = bank.deposit_amount_for(self.deposited_shares(&bank.mint))?;
+            let deposit_value = bank.market_value(deposited, price)?;
+            let borrowed = bank.borrow_amount_for(self.borrowed_shares(&bank.mint))?;
+
+            deposited_value = deposited_value
+                .checked_add(deposit_value)
+                .ok_or(ErrorCode::MathOverflow)?;
+            borrowed_value = borrowed_value
+                .checked_add(bank.market_value(borrowed, price)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+            let (max_ltv, liquidation_threshold) = match emode {
+                Some(emode) => (emode.max_ltv, emode.liquidation_threshold),
+                None => (bank.max_ltv, bank.liquidation_threshold),
+            };
+
+            allowed_borrow_value = allowed_borrow_value
+                .checked_add(apply_bps(deposit_value, max_ltv)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+            unhealthy_borrow_value = unhealthy_borrow_value
+                .checked_add(apply_bps(deposit_value, liquidation_threshold)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        let has_usdc = self.deposited_usdc_shares != 0 || self.borrowed_usdc_shares != 0;
+        let has_sol = self.deposited_sol_shares != 0 || self.borrowed_sol_shares != 0;
+        if (has_usdc && !seen_usdc) || (has_sol && !seen_sol) {
+            return Err(ErrorCode::InvalidRefreshAccounts.into());
+        }
+
+        self.deposited_value = deposited_value;
+        self.borrowed_value = borrowed_value;
+        self.allowed_borrow_value = allowed_borrow_value;
+        self.unhealthy_borrow_value = unhealthy_borrow_value;
+        self.last_refreshed_slot = slot;
+
+        Ok(())
+    }
+
+    /// The owner can do anything; the delegate only what its permission bits allow.
+    pub fn can_act(&self, signer: &Pubkey, permission: u8) -> bool {
+        *signer == self.owner
+            || (*signer == self.delegate
+                && self.delegate != Pubkey::default()
+                && self.delegate_permissions & permission != 0)
+    }
+
+    /// No deposits, borrows or isolated debt remain in any bank.
+    pub fn is_empty(&self) -> bool {
+        self.deposited_sol == 0
+            && self.deposited_sol_shares == 0
+            && self.borrowed_sol == 0
+            && self.borrowed_sol_shares == 0
+            && self.deposited_usdc == 0
+            && self.deposited_usdc_shares == 0
+            && self.borrowed_usdc == 0
+            && self.borrowed_usdc_shares == 0
+            && self.isolated_debt == 0
+    }
+
+    /// `(max_ltv, liquidation_threshold)` to revalue a change in `bank`'s
+    /// collateral with. The category's apply whenever `refresh` may have used
+    /// them, which can only shrink the allowance when it did not.
+    pub fn collateral_params(
+        &self,
+        bank: &Bank,
+        emode: Option<&EmodeCategory>,
+    ) -> Result<(u64, u64)> {
+        if self.emode_category == 0 || bank.emode_category != self.emode_category {
+            return Ok((bank.max_ltv, bank.liquidation_threshold));
+        }
+
+        match emode {
+            Some(emode) if emode.id == self.emode_category => {
+                Ok((emode.max_ltv, emode.liquidation_threshold))
+            }
+            _ => Err(ErrorCode::InvalidEmodeCategory.into()),
+        }
+    }
+
+    pub fn is_stale(&self, slot: u64) -> bool {
+        self.last_refreshed_slot != slot
+    }
+
+    pub fn is_liquidatable(&self) -> bool {
+        self.borrowed_value > self.unhealthy_borrow_value
+    }
+}
+
+pub fn apply_bps(value: u64, bps: u64) -> Result<u64> {
+    let result = (value as u128)
+        .checked_mul(bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+
+    u64::try_from(result).map_err(|_| ErrorCode::MathOverflow.into())
+}
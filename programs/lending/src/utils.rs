@@ -0,0 +1,206 @@
+use anchor_lang::{
+    prelude::*,
+    solana_program::{instruction::Instruction, program::invoke},
+    system_program::{transfer, Transfer},
+};
+use anchor_spl::{
+    token::spl_token::native_mint,
+    token_2022::spl_token_2022::{
+        self,
+        extension::{transfer_hook, StateWithExtensions},
+        onchain::invoke_transfer_checked,
+    },
+    token_interface::{
+        close_account, sync_native, transfer_checked, CloseAccount, SyncNative, TransferChecked,
+    },
+};
+
+use crate::{error::ErrorCode, Bank};
+
+/// Returns the transfer-hook program configured on a Token-2022 mint, if any.
+pub fn transfer_hook_program(mint: &AccountInfo) -> Result<Option<Pubkey>> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+
+    Ok(transfer_hook::get_program_id(&mint))
+}
+
+/// `transfer_checked` that resolves the mint's `ExtraAccountMetaList` from the
+/// context's remaining accounts and forwards them to the transfer hook. Hooked
+/// mints only move when the hook program is on the bank's allowlist.
+pub fn transfer_checked_with_hook<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    bank: &Bank,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    transfer_checked_with_allowed_hooks(ctx, &bank.transfer_hook_programs, amount, decimals)
+}
+
+/// `transfer_checked_with_hook` against an explicit hook allowlist, for mints
+/// such as reward mints that are not the bank's own.
+pub fn transfer_checked_with_allowed_hooks<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    allowed_hook_programs: &[Pubkey],
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    if let Some(program_id) = transfer_hook_program(&ctx.accounts.mint)? {
+        if !allowed_hook_programs.contains(&program_id) {
+            return Err(ErrorCode::TransferHookNotAllowed.into());
+        }
+    }
+
+    invoke_transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        &ctx.remaining_accounts,
+        amount,
+        decimals,
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+/// Takes the `count` accounts a hooked transfer needs off the front of
+/// `remaining_accounts` and returns them and the rest.
+pub fn take_hook_accounts<'info>(
+    remaining_accounts: &'info [AccountInfo<'info>],
+    count: u8,
+) -> Result<(&'info [AccountInfo<'info>], &'info [AccountInfo<'info>])> {
+    if remaining_accounts.len() < count as usize {
+        return Err(ErrorCode::InvalidHookAccounts.into());
+    }
+
+    Ok(remaining_accounts.split_at(count as usize))
+}
+
+/// Wraps `lamports` from `owner` in the temporary `wsol_account`, moves them
+/// into `owner`'s `user_ata` and closes the temporary account. Whatever
+/// `user_ata` already held is left alone.
+pub fn wrap_sol<'info>(
+    mint: &AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+    wsol_account: AccountInfo<'info>,
+    user_ata: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    lamports: u64,
+) -> Result<()> {
+    if mint.key() != native_mint::ID {
+        return Err(ErrorCode::NotNativeMint.into());
+    }
+
+    let cpi_accounts = Transfer {
+        from: owner.clone(),
+        to: wsol_account.clone(),
+    };
+    transfer(CpiContext::new(system_program, cpi_accounts), lamports)?;
+
+    let cpi_accounts = SyncNative {
+        account: wsol_account.clone(),
+    };
+    sync_native(CpiContext::new(token_program.clone(), cpi_accounts))?;
+
+    let cpi_accounts = TransferChecked {
+        from: wsol_account.clone(),
+        mint: mint.clone(),
+        to: user_ata,
+        authority: owner.clone(),
+    };
+    transfer_checked(
+        CpiContext::new(token_program.clone(), cpi_accounts),
+        lamports,
+        native_mint::DECIMALS,
+    )?;
+
+    let cpi_accounts = CloseAccount {
+        account: wsol_account,
+        destination: owner.clone(),
+        authority: owner,
+    };
+    close_account(CpiContext::new(token_program, cpi_accounts))
+}
+
+/// Moves `amount` wSOL out of `owner`'s `user_ata` into the temporary
+/// `wsol_account` and closes it, unwrapping exactly that amount plus the rent
+/// to `owner`. Whatever else `user_ata` holds stays wrapped.
+pub fn unwrap_sol<'info>(
+    mint: &AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+    wsol_account: AccountInfo<'info>,
+    user_ata: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if mint.key() != native_mint::ID {
+        return Err(ErrorCode::NotNativeMint.into());
+    }
+
+    let cpi_accounts = TransferChecked {
+        from: user_ata,
+        mint: mint.clone(),
+        to: wsol_account.clone(),
+        authority: owner.clone(),
+    };
+    transfer_checked(
+        CpiContext::new(token_program.clone(), cpi_accounts),
+        amount,
+        native_mint::DECIMALS,
+    )?;
+
+    let cpi_accounts = CloseAccount {
+        account: wsol_account,
+        destination: owner.clone(),
+        authority: owner,
+    };
+    close_account(CpiContext::new(token_program, cpi_accounts))
+}
+
+/// Invokes `swap_program` with caller-built instruction data, forwarding
+/// `accounts` as its account list with the signer and writable flags they
+/// arrived with.
+pub fn invoke_swap<'info>(
+    swap_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: swap_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+
+    let mut account_infos = accounts.to_vec();
+    account_infos.push(swap_program.clone());
+
+    invoke(&instruction, &account_infos).map_err(Into::into)
+}
+
+/// Closes a program-owned account that is not deserialized as an `Account`,
+/// moving its rent to `destination`.
+pub fn close_program_account(info: &AccountInfo, destination: &AccountInfo) -> Result<()> {
+    let lamports = info.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ErrorCode::MathOverflow)?;
+    **info.try_borrow_mut_lamports()? = 0;
+
+    info.assign(&System::id());
+    info.realloc(0, false).map_err(Into::into)
+}
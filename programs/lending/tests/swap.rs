@@ -0,0 +1,2181 @@
+//! Runs the lending program on a program-test bank with SOL and USDC
+//! markets, driving leverage, swap_collateral and repay_with_collateral
+//! through the mock-swap program.
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{entrypoint::ProgramResult, program_pack::Pack},
+    system_program, AccountSerialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::{
+    associated_token::{
+        self, get_associated_token_address,
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent,
+    },
+    token::{self, spl_token},
+};
+use lending::{
+    error::ErrorCode, Bank, Referrer, RewardSide, RiskTier, UpdateBankParams, User,
+    INSURANCE_WITHDRAWAL_TIMELOCK, SEED_BANK_ACCOUNT, SEED_COLLATERAL_MINT, SEED_COLLATERAL_VAULT,
+    SEED_EMODE_CATEGORY, SEED_FIXED_BORROW, SEED_REFERRER, SEED_REWARD_POOL, SEED_REWARD_VAULT,
+    SEED_TREASURY_ACCOUNT, SEED_USER_ACCOUNT, SEED_USER_REWARD, SOL_USD_FEED_ID, USDC_USD_FEED_ID,
+};
+use mock_swap::{SEED_POOL, SEED_VAULT};
+use pyth_solana_receiver_sdk::price_update::{
+    get_feed_id_from_hex, PriceFeedMessage, PriceUpdateV2, VerificationLevel,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+const SOL: u64 = 1_000_000_000;
+const USDC: u64 = 1_000_000;
+
+fn lending_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    lending::entry(program_id, Box::leak(Box::new(accounts.to_vec())), data)
+}
+
+fn mock_swap_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    mock_swap::entry(program_id, Box::leak(Box::new(accounts.to_vec())), data)
+}
+
+/// A SOL bank at $100 and a USDC bank at $1, both routing swaps through
+/// mock-swap, with pools converting between them at those prices.
+struct Market {
+    context: ProgramTestContext,
+    mint_authority: Keypair,
+    sol_mint: Pubkey,
+    usdc_mint: Pubkey,
+    sol_price: Pubkey,
+    usdc_price: Pubkey,
+}
+
+impl Market {
+    async fn new() -> Self {
+        let mut program_test = ProgramTest::new("lending", lending::ID, processor!(lending_entry));
+        program_test.add_program("mock_swap", mock_swap::ID, processor!(mock_swap_entry));
+
+        let mint_authority = Keypair::new();
+        let sol_mint = Pubkey::new_unique();
+        let usdc_mint = Pubkey::new_unique();
+        for (mint, decimals) in [(sol_mint, 9), (usdc_mint, 6)] {
+            program_test.add_account(mint, mint_account(&mint_authority.pubkey(), decimals));
+        }
+
+        let mut market = Self {
+            context: program_test.start_with_context().await,
+            mint_authority,
+            sol_mint,
+            usdc_mint,
+            sol_price: Pubkey::new_unique(),
+            usdc_price: Pubkey::new_unique(),
+        };
+        market
+            .set_price(market.sol_price, SOL_USD_FEED_ID, 100)
+            .await;
+        market
+            .set_price(market.usdc_price, USDC_USD_FEED_ID, 1)
+            .await;
+
+        market.init_market().await;
+        for (mint, feed_id) in [(sol_mint, SOL_USD_FEED_ID), (usdc_mint, USDC_USD_FEED_ID)] {
+            market.init_bank(mint).await;
+            let instruction =
+                market.set_bank_oracle_instruction(&market.context.payer.pubkey(), mint, feed_id);
+            market.process(instruction, &[]).await;
+        }
+        // 1 USDC buys 0.01 SOL and 1 SOL sells for 100 USDC, in base units.
+        market
+            .init_pool(usdc_mint, sol_mint, 10, 1, 100 * SOL)
+            .await;
+        market
+            .init_pool(sol_mint, usdc_mint, 1, 10, 10_000 * USDC)
+            .await;
+
+        let lender = market.new_wallet().await;
+        market.init_user(&lender).await;
+        market.deposit(&lender, usdc_mint, 1_000 * USDC, None).await;
+
+        market
+    }
+
+    async fn process(&mut self, instruction: Instruction, signers: &[&Keypair]) {
+        self.try_process(instruction, signers).await.unwrap();
+    }
+
+    async fn try_process(
+        &mut self,
+        instruction: Instruction,
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), BanksClientError> {
+        self.try_process_all(&[instruction], signers).await
+    }
+
+    /// Sends `instructions` in one transaction, so they share a slot.
+    async fn try_process_all(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), BanksClientError> {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let blockhash = self
+            .context
+            .banks_client
+            .get_latest_blockhash()
+            .await
+            .unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    async fn account<T: AccountDeserialize>(&mut self, address: Pubkey) -> T {
+        let account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    async fn token_balance(&mut self, address: Pubkey) -> u64 {
+        let account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+
+    async fn set_price(&mut self, address: Pubkey, feed_id: &str, dollars: i64) {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        let price = dollars * 100_000_000;
+        let price_update = PriceUpdateV2 {
+            write_authority: Pubkey::default(),
+            verification_level: VerificationLevel::Full,
+            price_message: PriceFeedMessage {
+                feed_id: get_feed_id_from_hex(feed_id).unwrap(),
+                price,
+                conf: 0,
+                exponent: -8,
+                publish_time: clock.unix_timestamp,
+                prev_publish_time: clock.unix_timestamp,
+                ema_price: price,
+                ema_conf: 0,
+            },
+            posted_slot: clock.slot,
+        };
+
+        let mut data = Vec::new();
+        price_update.try_serialize(&mut data).unwrap();
+        self.context.set_account(
+            &address,
+            &Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: pyth_solana_receiver_sdk::ID,
+                ..Default::default()
+            }
+            .into(),
+        );
+    }
+
+    async fn new_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = Pubkey::new_unique();
+        self.context.set_account(
+            &mint,
+            &mint_account(&self.mint_authority.pubkey(), decimals).into(),
+        );
+        mint
+    }
+
+    async fn new_wallet(&mut self) -> Keypair {
+        let wallet = Keypair::new();
+        self.process(
+            anchor_lang::solana_program::system_instruction::transfer(
+                &self.context.payer.pubkey(),
+                &wallet.pubkey(),
+                10 * SOL,
+            ),
+            &[],
+        )
+        .await;
+        wallet
+    }
+
+    /// Mints `amount` of `mint` into `owner`'s associated token account.
+    async fn fund(&mut self, owner: &Pubkey, mint: Pubkey, amount: u64) {
+        self.process(
+            create_associated_token_account_idempotent(
+                &self.context.payer.pubkey(),
+                owner,
+                &mint,
+                &token::ID,
+            ),
+            &[],
+        )
+        .await;
+
+        self.mint_to(get_associated_token_address(owner, &mint), mint, amount)
+            .await;
+    }
+
+    async fn mint_to(&mut self, account: Pubkey, mint: Pubkey, amount: u64) {
+        let mint_authority = self.mint_authority.insecure_clone();
+        self.process(
+            spl_token::instruction::mint_to(
+                &token::ID,
+                &mint,
+                &account,
+                &mint_authority.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap(),
+            &[&mint_authority],
+        )
+        .await;
+    }
+
+    async fn init_market(&mut self) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::InitMarket {
+                    signer: self.context.payer.pubkey(),
+                    market: market_address(),
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::InitMarket {}.data(),
+            },
+            &[],
+        )
+        .await;
+    }
+
+    fn set_bank_oracle_instruction(
+        &self,
+        signer: &Pubkey,
+        mint: Pubkey,
+        feed_id: &str,
+    ) -> Instruction {
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::SetBankOracle {
+                signer: *signer,
+                market: market_address(),
+                mint,
+                bank: bank_address(&mint),
+            }
+            .to_account_metas(None),
+            data: lending::instruction::SetBankOracle {
+                feed_id: get_feed_id_from_hex(feed_id).unwrap(),
+            }
+            .data(),
+        }
+    }
+
+    async fn init_bank(&mut self, mint: Pubkey) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::InitBank {
+                    signer: self.context.payer.pubkey(),
+                    mint,
+                    bank: bank_address(&mint),
+                    treasury: treasury_address(&mint),
+                    collateral_mint: receipt_mint_address(&mint),
+                    collateral_vault: receipt_vault_address(&mint),
+                    fee_vault: fee_vault_address(&mint),
+                    insurance_vault: insurance_vault_address(&mint),
+                    token_program: token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::InitBank {
+                    liquidation_threshold: 8000,
+                    max_ltv: 7500,
+                    allowed_hook_programs: Vec::new(),
+                }
+                .data(),
+            },
+            &[],
+        )
+        .await;
+
+        self.update_bank(
+            mint,
+            UpdateBankParams {
+                swap_program: Some(mock_swap::ID),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    async fn update_bank(&mut self, mint: Pubkey, params: UpdateBankParams) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::UpdateBank {
+                    signer: self.context.payer.pubkey(),
+                    mint,
+                    bank: bank_address(&mint),
+                }
+                .to_account_metas(None),
+                data: lending::instruction::UpdateBank { params }.data(),
+            },
+            &[],
+        )
+        .await;
+    }
+
+    async fn init_pool(
+        &mut self,
+        mint_in: Pubkey,
+        mint_out: Pubkey,
+        rate_numerator: u64,
+        rate_denominator: u64,
+        liquidity: u64,
+    ) {
+        let pool = pool_address(&mint_in, &mint_out);
+        self.process(
+            Instruction {
+                program_id: mock_swap::ID,
+                accounts: mock_swap::accounts::InitPool {
+                    signer: self.context.payer.pubkey(),
+                    mint_in,
+                    mint_out,
+                    pool,
+                    vault_in: pool_vault_address(&pool, &mint_in),
+                    vault_out: pool_vault_address(&pool, &mint_out),
+                    token_program: token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: mock_swap::instruction::InitPool {
+                    rate_numerator,
+                    rate_denominator,
+                }
+                .data(),
+            },
+            &[],
+        )
+        .await;
+
+        let mint_authority = self.mint_authority.insecure_clone();
+        self.process(
+            spl_token::instruction::mint_to(
+                &token::ID,
+                &mint_out,
+                &pool_vault_address(&pool, &mint_out),
+                &mint_authority.pubkey(),
+                &[],
+                liquidity,
+            )
+            .unwrap(),
+            &[&mint_authority],
+        )
+        .await;
+    }
+
+    async fn init_user(&mut self, wallet: &Keypair) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::InitUser {
+                    signer: wallet.pubkey(),
+                    user: user_address(&wallet.pubkey()),
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::InitUser {
+                    mint_usdc: self.usdc_mint,
+                    mint_sol: self.sol_mint,
+                    sub_account: 0,
+                }
+                .data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    async fn init_referrer(&mut self, owner: Pubkey, mint: Pubkey) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::InitReferrer {
+                    signer: self.context.payer.pubkey(),
+                    owner,
+                    mint,
+                    bank: bank_address(&mint),
+                    referrer: referrer_address(&owner, &mint),
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::InitReferrer {}.data(),
+            },
+            &[],
+        )
+        .await;
+    }
+
+    async fn deposit(
+        &mut self,
+        wallet: &Keypair,
+        mint: Pubkey,
+        amount: u64,
+        referrer: Option<Pubkey>,
+    ) {
+        self.fund(&wallet.pubkey(), mint, amount).await;
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::Deposit {
+                    signer: wallet.pubkey(),
+                    mint,
+                    bank: bank_address(&mint),
+                    treasury: treasury_address(&mint),
+                    collateral_mint: receipt_mint_address(&mint),
+                    collateral_vault: receipt_vault_address(&mint),
+                    user: user_address(&wallet.pubkey()),
+                    referrer: referrer.map(|owner| referrer_address(&owner, &mint)),
+                    user_ata: get_associated_token_address(&wallet.pubkey(), &mint),
+                    owner: wallet.pubkey(),
+                    receipt_ata: receipt_ata_address(&wallet.pubkey(), &mint),
+                    token_program: token::ID,
+                    associated_token_program: associated_token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::Deposit { amount }.data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    /// Locks every receipt in the wallet as the user's collateral.
+    async fn lock_receipts(&mut self, wallet: &Keypair, mint: Pubkey) {
+        let instruction = self.lock_receipts_instruction(wallet, mint).await;
+        self.process(instruction, &[wallet]).await;
+    }
+
+    async fn lock_receipts_instruction(&mut self, wallet: &Keypair, mint: Pubkey) -> Instruction {
+        let shares = self
+            .token_balance(receipt_ata_address(&wallet.pubkey(), &mint))
+            .await;
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::DepositReceiptAsCollateral {
+                signer: wallet.pubkey(),
+                mint,
+                bank: bank_address(&mint),
+                collateral_mint: receipt_mint_address(&mint),
+                collateral_vault: receipt_vault_address(&mint),
+                user: user_address(&wallet.pubkey()),
+                user_collateral_ata: receipt_ata_address(&wallet.pubkey(), &mint),
+                token_program: token::ID,
+                associated_token_program: associated_token::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::DepositReceiptAsCollateral { shares }.data(),
+        }
+    }
+
+    fn unlock_receipts_instruction(
+        &self,
+        wallet: &Keypair,
+        mint: Pubkey,
+        shares: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::WithdrawReceiptCollateral {
+                signer: wallet.pubkey(),
+                mint,
+                bank: bank_address(&mint),
+                collateral_mint: receipt_mint_address(&mint),
+                collateral_vault: receipt_vault_address(&mint),
+                user: user_address(&wallet.pubkey()),
+                emode_category: None,
+                user_collateral_ata: receipt_ata_address(&wallet.pubkey(), &mint),
+                price_update: self.price_for(&mint),
+                token_program: token::ID,
+                associated_token_program: associated_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::WithdrawReceiptCollateral { shares }.data(),
+        }
+    }
+
+    /// `close_user` with the given UserReward addresses as remaining accounts.
+    fn close_user_instruction(&self, wallet: &Keypair, user_rewards: &[Pubkey]) -> Instruction {
+        let user = user_address(&wallet.pubkey());
+        let mut accounts = lending::accounts::CloseUser {
+            signer: wallet.pubkey(),
+            user,
+            usdc_bank: bank_address(&self.usdc_mint),
+            sol_bank: bank_address(&self.sol_mint),
+            usdc_fixed_borrow: fixed_borrow_address(&user, &self.usdc_mint),
+            sol_fixed_borrow: fixed_borrow_address(&user, &self.sol_mint),
+        }
+        .to_account_metas(None);
+        accounts.extend(
+            user_rewards
+                .iter()
+                .map(|user_reward| AccountMeta::new(*user_reward, false)),
+        );
+
+        Instruction {
+            program_id: lending::ID,
+            accounts,
+            data: lending::instruction::CloseUser {}.data(),
+        }
+    }
+
+    async fn redeem_receipt(&mut self, wallet: &Keypair, mint: Pubkey, shares: u64) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::RedeemReceipt {
+                    signer: wallet.pubkey(),
+                    mint,
+                    bank: bank_address(&mint),
+                    treasury: treasury_address(&mint),
+                    collateral_mint: receipt_mint_address(&mint),
+                    receipt_ata: receipt_ata_address(&wallet.pubkey(), &mint),
+                    user_ata: get_associated_token_address(&wallet.pubkey(), &mint),
+                    token_program: token::ID,
+                    associated_token_program: associated_token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::RedeemReceipt { shares }.data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    /// Moves the clock forward by `seconds`, and a slot so a retried
+    /// transaction gets a fresh blockhash.
+    async fn warp(&mut self, seconds: i64) {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        self.context.warp_to_slot(clock.slot + 1).unwrap();
+        let mut warped: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        warped.unix_timestamp = clock.unix_timestamp + seconds;
+        self.context.set_sysvar(&warped);
+    }
+
+    /// Creates a supply-side pool on `mint`'s bank and funds it with `amount`
+    /// of `reward_mint`, emitted at `emission_per_second`.
+    async fn init_reward_pool(
+        &mut self,
+        mint: Pubkey,
+        reward_mint: Pubkey,
+        amount: u64,
+        emission_per_second: u64,
+    ) -> Pubkey {
+        let payer = self.context.payer.pubkey();
+        let pool = reward_pool_address(&mint, &reward_mint);
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::InitRewardPool {
+                    signer: payer,
+                    mint,
+                    bank: bank_address(&mint),
+                    reward_mint,
+                    reward_pool: pool,
+                    reward_vault: reward_vault_address(&pool),
+                    token_program: token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::InitRewardPool {
+                    side: RewardSide::Supply,
+                    allowed_hook_programs: Vec::new(),
+                }
+                .data(),
+            },
+            &[],
+        )
+        .await;
+
+        self.fund(&payer, reward_mint, amount).await;
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::FundRewardPool {
+                    signer: payer,
+                    mint,
+                    bank: bank_address(&mint),
+                    reward_mint,
+                    reward_pool: pool,
+                    reward_vault: reward_vault_address(&pool),
+                    signer_token_account: get_associated_token_address(&payer, &reward_mint),
+                    token_program: token::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::FundRewardPool {
+                    amount,
+                    emission_per_second,
+                }
+                .data(),
+            },
+            &[],
+        )
+        .await;
+
+        pool
+    }
+
+    async fn init_user_reward(&mut self, wallet: &Keypair, mint: Pubkey, pool: Pubkey) {
+        let user = user_address(&wallet.pubkey());
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::InitUserReward {
+                    signer: wallet.pubkey(),
+                    user,
+                    bank: bank_address(&mint),
+                    reward_pool: pool,
+                    user_reward: user_reward_address(&pool, &user),
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::InitUserReward {}.data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    async fn claim_rewards(&mut self, wallet: &Keypair, mint: Pubkey, reward_mint: Pubkey) {
+        let user = user_address(&wallet.pubkey());
+        let pool = reward_pool_address(&mint, &reward_mint);
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::ClaimRewards {
+                    signer: wallet.pubkey(),
+                    user,
+                    mint,
+                    bank: bank_address(&mint),
+                    reward_mint,
+                    reward_pool: pool,
+                    reward_vault: reward_vault_address(&pool),
+                    user_reward: user_reward_address(&pool, &user),
+                    signer_ata: get_associated_token_address(&wallet.pubkey(), &reward_mint),
+                    token_program: token::ID,
+                    associated_token_program: associated_token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::ClaimRewards {}.data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    /// Refreshes the user against the SOL and USDC banks, then borrows
+    /// `amount` of `mint` against the other one.
+    async fn borrow(&mut self, wallet: &Keypair, mint: Pubkey, amount: u64) {
+        self.try_borrow(wallet, mint, amount, None).await.unwrap();
+    }
+
+    async fn try_borrow(
+        &mut self,
+        wallet: &Keypair,
+        mint: Pubkey,
+        amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> std::result::Result<(), BanksClientError> {
+        let owner = wallet.pubkey();
+        let collateral_mint = if mint == self.usdc_mint {
+            self.sol_mint
+        } else {
+            self.usdc_mint
+        };
+        let borrow = Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::Borrow {
+                signer: owner,
+                mint,
+                bank: bank_address(&mint),
+                collateral_bank: bank_address(&collateral_mint),
+                treasury: treasury_address(&mint),
+                user: user_address(&owner),
+                referrer: referrer.map(|referrer| referrer_address(&referrer, &mint)),
+                owner,
+                user_ata: get_associated_token_address(&owner, &mint),
+                price_update: self.price_for(&mint),
+                token_program: token::ID,
+                associated_token_program: associated_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::Borrow { amount }.data(),
+        };
+        let refresh = self.refresh_user_instruction(&owner).await;
+        self.try_process_all(&[refresh, borrow], &[wallet]).await
+    }
+
+    async fn refresh_user_instruction(&mut self, owner: &Pubkey) -> Instruction {
+        let user = user_address(owner);
+        let User { emode_category, .. } = self.account(user).await;
+        let mut accounts = lending::accounts::RefreshUser {
+            user,
+            emode_category: (emode_category != 0).then(|| emode_address(emode_category)),
+        }
+        .to_account_metas(None);
+        accounts.extend(self.position_accounts(&user));
+
+        Instruction {
+            program_id: lending::ID,
+            accounts,
+            data: lending::instruction::RefreshUser {}.data(),
+        }
+    }
+
+    /// Bank, price update and fixed borrow of both markets, as `refresh_user`
+    /// and `set_emode` take them.
+    fn position_accounts(&self, user: &Pubkey) -> Vec<AccountMeta> {
+        [self.sol_mint, self.usdc_mint]
+            .into_iter()
+            .flat_map(|mint| {
+                [
+                    AccountMeta::new_readonly(bank_address(&mint), false),
+                    AccountMeta::new_readonly(self.price_for(&mint), false),
+                    AccountMeta::new_readonly(fixed_borrow_address(user, &mint), false),
+                ]
+            })
+            .collect()
+    }
+
+    fn init_emode_category_instruction(
+        &self,
+        signer: &Pubkey,
+        id: u8,
+        max_ltv: u64,
+        liquidation_threshold: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::InitEmodeCategory {
+                signer: *signer,
+                market: market_address(),
+                emode_category: emode_address(id),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::InitEmodeCategory {
+                id,
+                max_ltv,
+                liquidation_threshold,
+                liquidation_bonus: 200,
+            }
+            .data(),
+        }
+    }
+
+    async fn set_bank_emode(&mut self, mint: Pubkey, category: u8) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::SetBankEmode {
+                    signer: self.context.payer.pubkey(),
+                    market: market_address(),
+                    mint,
+                    bank: bank_address(&mint),
+                    emode_category: Some(emode_address(category)),
+                }
+                .to_account_metas(None),
+                data: lending::instruction::SetBankEmode { category }.data(),
+            },
+            &[],
+        )
+        .await;
+    }
+
+    async fn set_emode(&mut self, wallet: &Keypair, category: u8) {
+        let user = user_address(&wallet.pubkey());
+        let mut accounts = lending::accounts::SetEmode {
+            signer: wallet.pubkey(),
+            user,
+            emode_category: Some(emode_address(category)),
+        }
+        .to_account_metas(None);
+        accounts.extend(self.position_accounts(&user));
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts,
+                data: lending::instruction::SetEmode { category }.data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    async fn claim_referrer_fees(&mut self, referrer: &Keypair, mint: Pubkey) {
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::ClaimReferrerFees {
+                    signer: referrer.pubkey(),
+                    mint,
+                    bank: bank_address(&mint),
+                    treasury: treasury_address(&mint),
+                    referrer: referrer_address(&referrer.pubkey(), &mint),
+                    signer_ata: get_associated_token_address(&referrer.pubkey(), &mint),
+                    token_program: token::ID,
+                    associated_token_program: associated_token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: lending::instruction::ClaimReferrerFees {}.data(),
+            },
+            &[referrer],
+        )
+        .await;
+    }
+
+    /// Repays up to `amount` of `owner`'s USDC debt from `liquidator`'s
+    /// wallet and seizes SOL collateral for it.
+    async fn liquidate(
+        &mut self,
+        liquidator: &Keypair,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let user = user_address(owner);
+        let (collateral_mint, borrowed_mint) = (self.sol_mint, self.usdc_mint);
+        let instruction = Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::Liquidate {
+                liquidator: liquidator.pubkey(),
+                collateral_mint,
+                borrowed_mint,
+                collateral_bank: bank_address(&collateral_mint),
+                borrowed_bank: bank_address(&borrowed_mint),
+                collateral_treasury: treasury_address(&collateral_mint),
+                borrowed_treasury: treasury_address(&borrowed_mint),
+                collateral_receipt_mint: receipt_mint_address(&collateral_mint),
+                collateral_receipt_vault: receipt_vault_address(&collateral_mint),
+                fee_vault: fee_vault_address(&collateral_mint),
+                insurance_vault: insurance_vault_address(&collateral_mint),
+                user,
+                fixed_borrow: fixed_borrow_address(&user, &borrowed_mint),
+                collateral_fixed_borrow: fixed_borrow_address(&user, &collateral_mint),
+                emode_category: None,
+                collateral_price_update: self.price_for(&collateral_mint),
+                borrowed_price_update: self.price_for(&borrowed_mint),
+                liquidator_collateral_ata: get_associated_token_address(
+                    &liquidator.pubkey(),
+                    &collateral_mint,
+                ),
+                liquidator_borrowed_ata: get_associated_token_address(
+                    &liquidator.pubkey(),
+                    &borrowed_mint,
+                ),
+                token_program: token::ID,
+                associated_token_program: associated_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::Liquidate { amount }.data(),
+        };
+
+        self.try_process(instruction, &[liquidator]).await
+    }
+
+    /// Repays `amount` of `owner`'s USDC debt from `payer`'s wallet.
+    fn repay_obligation_instruction(
+        &self,
+        payer: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let user = user_address(owner);
+        let mint = self.usdc_mint;
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::RepayObligation {
+                repay: lending::accounts::Repay {
+                    signer: *payer,
+                    mint,
+                    bank: bank_address(&mint),
+                    collateral_bank: None,
+                    treasury: treasury_address(&mint),
+                    user,
+                    fixed_borrow: fixed_borrow_address(&user, &mint),
+                    user_ata: get_associated_token_address(payer, &mint),
+                    token_program: token::ID,
+                    associated_token_program: associated_token::ID,
+                    system_program: system_program::ID,
+                },
+            }
+            .to_account_metas(None),
+            data: lending::instruction::RepayObligation { amount }.data(),
+        }
+    }
+
+    async fn set_third_party_repays(&mut self, wallet: &Keypair, allowed: bool) {
+        let owner = wallet.pubkey();
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts: lending::accounts::SetThirdPartyRepays {
+                    signer: owner,
+                    user: user_address(&owner),
+                }
+                .to_account_metas(None),
+                data: lending::instruction::SetThirdPartyRepays { allowed }.data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    fn socialize_loss_instruction(&self, signer: &Pubkey, owner: &Pubkey) -> Instruction {
+        let user = user_address(owner);
+        let mint = self.usdc_mint;
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::SocializeLoss {
+                signer: *signer,
+                mint,
+                bank: bank_address(&mint),
+                collateral_bank: None,
+                treasury: treasury_address(&mint),
+                fee_vault: fee_vault_address(&mint),
+                insurance_vault: insurance_vault_address(&mint),
+                user,
+                fixed_borrow: fixed_borrow_address(&user, &mint),
+                token_program: token::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::SocializeLoss {}.data(),
+        }
+    }
+
+    fn request_insurance_withdrawal_instruction(
+        &self,
+        signer: &Pubkey,
+        mint: Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::RequestInsuranceWithdrawal {
+                signer: *signer,
+                mint,
+                bank: bank_address(&mint),
+            }
+            .to_account_metas(None),
+            data: lending::instruction::RequestInsuranceWithdrawal { amount }.data(),
+        }
+    }
+
+    fn withdraw_insurance_instruction(&self, signer: &Pubkey, mint: Pubkey) -> Instruction {
+        Instruction {
+            program_id: lending::ID,
+            accounts: lending::accounts::WithdrawInsurance {
+                signer: *signer,
+                mint,
+                bank: bank_address(&mint),
+                insurance_vault: insurance_vault_address(&mint),
+                signer_ata: get_associated_token_address(signer, &mint),
+                token_program: token::ID,
+                associated_token_program: associated_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: lending::instruction::WithdrawInsurance {}.data(),
+        }
+    }
+
+    fn price_for(&self, mint: &Pubkey) -> Pubkey {
+        if *mint == self.usdc_mint {
+            self.usdc_price
+        } else {
+            self.sol_price
+        }
+    }
+
+    /// The mock-swap `swap` accounts selling `amount_in` of the signer's
+    /// `mint_in` for `mint_out`.
+    fn swap_accounts(
+        &self,
+        wallet: &Pubkey,
+        mint_in: Pubkey,
+        mint_out: Pubkey,
+        amount_in: u64,
+    ) -> (Vec<AccountMeta>, Vec<u8>) {
+        let pool = pool_address(&mint_in, &mint_out);
+        let accounts = mock_swap::accounts::Swap {
+            signer: *wallet,
+            pool,
+            mint_in,
+            mint_out,
+            vault_in: pool_vault_address(&pool, &mint_in),
+            vault_out: pool_vault_address(&pool, &mint_out),
+            source: get_associated_token_address(wallet, &mint_in),
+            destination: get_associated_token_address(wallet, &mint_out),
+            token_program: token::ID,
+        }
+        .to_account_metas(None);
+        let data = mock_swap::instruction::Swap {
+            amount_in,
+            min_amount_out: 0,
+        }
+        .data();
+
+        (accounts, data)
+    }
+
+    async fn leverage(
+        &mut self,
+        wallet: &Keypair,
+        mint: Pubkey,
+        deposit_mint: Pubkey,
+        amount: u64,
+        min_collateral_out: u64,
+        referrer: Option<Pubkey>,
+    ) -> std::result::Result<(), BanksClientError> {
+        let owner = wallet.pubkey();
+        let user = user_address(&owner);
+        let (swap_accounts, swap_data) = self.swap_accounts(&owner, mint, deposit_mint, amount);
+
+        let mut accounts = lending::accounts::Leverage {
+            signer: owner,
+            user,
+            fixed_borrow: fixed_borrow_address(&user, &mint),
+            collateral_fixed_borrow: fixed_borrow_address(&user, &deposit_mint),
+            emode_category: None,
+            mint,
+            bank: bank_address(&mint),
+            treasury: treasury_address(&mint),
+            user_ata: get_associated_token_address(&owner, &mint),
+            price_update: self.price_for(&mint),
+            deposit_mint,
+            collateral_bank: bank_address(&deposit_mint),
+            collateral_treasury: treasury_address(&deposit_mint),
+            receipt_mint: receipt_mint_address(&deposit_mint),
+            receipt_vault: receipt_vault_address(&deposit_mint),
+            collateral_ata: get_associated_token_address(&owner, &deposit_mint),
+            collateral_price_update: self.price_for(&deposit_mint),
+            referrer: referrer.map(|owner| referrer_address(&owner, &mint)),
+            swap_program: mock_swap::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        accounts.extend(swap_accounts);
+
+        self.try_process(
+            Instruction {
+                program_id: lending::ID,
+                accounts,
+                data: lending::instruction::Leverage {
+                    amount,
+                    min_collateral_out,
+                    swap_data,
+                    borrow_hook_accounts: 0,
+                    deposit_hook_accounts: 0,
+                }
+                .data(),
+            },
+            &[wallet],
+        )
+        .await
+    }
+
+    fn swap_collateral_accounts(
+        &self,
+        owner: &Pubkey,
+        mint: Pubkey,
+        target_mint: Pubkey,
+    ) -> lending::accounts::SwapCollateral {
+        let user = user_address(owner);
+        lending::accounts::SwapCollateral {
+            signer: *owner,
+            user,
+            fixed_borrow: fixed_borrow_address(&user, &mint),
+            target_fixed_borrow: fixed_borrow_address(&user, &target_mint),
+            emode_category: None,
+            mint,
+            bank: bank_address(&mint),
+            treasury: treasury_address(&mint),
+            collateral_mint: receipt_mint_address(&mint),
+            collateral_vault: receipt_vault_address(&mint),
+            user_ata: get_associated_token_address(owner, &mint),
+            price_update: self.price_for(&mint),
+            target_mint,
+            target_bank: bank_address(&target_mint),
+            target_treasury: treasury_address(&target_mint),
+            target_collateral_mint: receipt_mint_address(&target_mint),
+            target_collateral_vault: receipt_vault_address(&target_mint),
+            target_ata: get_associated_token_address(owner, &target_mint),
+            target_price_update: self.price_for(&target_mint),
+            swap_program: mock_swap::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    async fn swap_collateral(
+        &mut self,
+        wallet: &Keypair,
+        mint: Pubkey,
+        target_mint: Pubkey,
+        amount: u64,
+    ) {
+        let owner = wallet.pubkey();
+        let (swap_accounts, swap_data) = self.swap_accounts(&owner, mint, target_mint, amount);
+
+        let mut accounts = self
+            .swap_collateral_accounts(&owner, mint, target_mint)
+            .to_account_metas(None);
+        accounts.extend(swap_accounts);
+
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts,
+                data: lending::instruction::SwapCollateral {
+                    amount,
+                    min_amount_out: 0,
+                    swap_data,
+                    withdraw_hook_accounts: 0,
+                    deposit_hook_accounts: 0,
+                }
+                .data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+
+    async fn repay_with_collateral(
+        &mut self,
+        wallet: &Keypair,
+        mint: Pubkey,
+        target_mint: Pubkey,
+        amount: u64,
+    ) {
+        let owner = wallet.pubkey();
+        let (swap_accounts, swap_data) = self.swap_accounts(&owner, mint, target_mint, amount);
+
+        let mut accounts = lending::accounts::RepayWithCollateral {
+            swap: self.swap_collateral_accounts(&owner, mint, target_mint),
+        }
+        .to_account_metas(None);
+        accounts.extend(swap_accounts);
+
+        self.process(
+            Instruction {
+                program_id: lending::ID,
+                accounts,
+                data: lending::instruction::RepayWithCollateral {
+                    amount,
+                    min_amount_out: 0,
+                    swap_data,
+                    withdraw_hook_accounts: 0,
+                    repay_hook_accounts: 0,
+                }
+                .data(),
+            },
+            &[wallet],
+        )
+        .await;
+    }
+}
+
+fn mint_account(mint_authority: &Pubkey, decimals: u8) -> Account {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        mint_authority: Some(*mint_authority).into(),
+        decimals,
+        is_initialized: true,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: token::ID,
+        ..Default::default()
+    }
+}
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &lending::ID).0
+}
+
+fn market_address() -> Pubkey {
+    pda(&[lending::SEED_MARKET])
+}
+
+fn bank_address(mint: &Pubkey) -> Pubkey {
+    pda(&[SEED_BANK_ACCOUNT, mint.as_ref()])
+}
+
+fn treasury_address(mint: &Pubkey) -> Pubkey {
+    pda(&[SEED_TREASURY_ACCOUNT, mint.as_ref()])
+}
+
+fn receipt_mint_address(mint: &Pubkey) -> Pubkey {
+    pda(&[SEED_COLLATERAL_MINT, mint.as_ref()])
+}
+
+fn receipt_vault_address(mint: &Pubkey) -> Pubkey {
+    pda(&[SEED_COLLATERAL_VAULT, mint.as_ref()])
+}
+
+fn receipt_ata_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(owner, &receipt_mint_address(mint))
+}
+
+fn fee_vault_address(mint: &Pubkey) -> Pubkey {
+    pda(&[lending::SEED_FEE_VAULT, mint.as_ref()])
+}
+
+fn insurance_vault_address(mint: &Pubkey) -> Pubkey {
+    pda(&[lending::SEED_INSURANCE_VAULT, mint.as_ref()])
+}
+
+fn user_address(owner: &Pubkey) -> Pubkey {
+    pda(&[SEED_USER_ACCOUNT, owner.as_ref(), &0u16.to_le_bytes()])
+}
+
+fn emode_address(id: u8) -> Pubkey {
+    pda(&[SEED_EMODE_CATEGORY, &[id]])
+}
+
+fn fixed_borrow_address(user: &Pubkey, mint: &Pubkey) -> Pubkey {
+    pda(&[SEED_FIXED_BORROW, user.as_ref(), mint.as_ref()])
+}
+
+fn referrer_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    pda(&[SEED_REFERRER, owner.as_ref(), mint.as_ref()])
+}
+
+fn reward_pool_address(mint: &Pubkey, reward_mint: &Pubkey) -> Pubkey {
+    pda(&[
+        SEED_REWARD_POOL,
+        mint.as_ref(),
+        reward_mint.as_ref(),
+        &[RewardSide::Supply as u8],
+    ])
+}
+
+fn reward_vault_address(pool: &Pubkey) -> Pubkey {
+    pda(&[SEED_REWARD_VAULT, pool.as_ref()])
+}
+
+fn user_reward_address(pool: &Pubkey, user: &Pubkey) -> Pubkey {
+    pda(&[SEED_USER_REWARD, pool.as_ref(), user.as_ref()])
+}
+
+fn pool_address(mint_in: &Pubkey, mint_out: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[SEED_POOL, mint_in.as_ref(), mint_out.as_ref()],
+        &mock_swap::ID,
+    )
+    .0
+}
+
+fn pool_vault_address(pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[SEED_VAULT, pool.as_ref(), mint.as_ref()], &mock_swap::ID).0
+}
+
+#[tokio::test]
+async fn leverage_borrows_swaps_and_deposits() {
+    let mut market = Market::new().await;
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, SOL, None).await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+
+    market
+        .leverage(
+            &wallet,
+            market.usdc_mint,
+            market.sol_mint,
+            50 * USDC,
+            SOL / 2,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc, 50 * USDC);
+    assert_eq!(user.deposited_sol, 3 * SOL / 2);
+    assert_eq!(user.borrowed_value, 50 * USDC);
+    assert_eq!(user.deposited_value, 150 * USDC);
+
+    let usdc_bank: Bank = market.account(bank_address(&market.usdc_mint)).await;
+    assert_eq!(usdc_bank.total_borrowed, 50 * USDC);
+    let sol_bank: Bank = market.account(bank_address(&market.sol_mint)).await;
+    assert_eq!(sol_bank.total_deposit, 3 * SOL / 2);
+    assert_eq!(
+        market
+            .token_balance(receipt_vault_address(&market.sol_mint))
+            .await,
+        user.deposited_sol_shares
+    );
+}
+
+#[tokio::test]
+async fn swap_collateral_moves_the_deposit() {
+    let mut market = Market::new().await;
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, SOL, None).await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+
+    market
+        .swap_collateral(&wallet, market.sol_mint, market.usdc_mint, SOL / 4)
+        .await;
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.deposited_sol, 3 * SOL / 4);
+    assert_eq!(user.deposited_usdc, 25 * USDC);
+    assert_eq!(user.deposited_value, 100 * USDC);
+
+    let sol_bank: Bank = market.account(bank_address(&market.sol_mint)).await;
+    assert_eq!(sol_bank.total_deposit, 3 * SOL / 4);
+    assert_eq!(
+        market
+            .token_balance(treasury_address(&market.sol_mint))
+            .await,
+        3 * SOL / 4
+    );
+}
+
+#[tokio::test]
+async fn repay_with_collateral_pays_down_debt() {
+    let mut market = Market::new().await;
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, SOL, None).await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+    market
+        .leverage(
+            &wallet,
+            market.usdc_mint,
+            market.sol_mint,
+            50 * USDC,
+            0,
+            None,
+        )
+        .await
+        .unwrap();
+
+    market
+        .repay_with_collateral(&wallet, market.sol_mint, market.usdc_mint, SOL / 5)
+        .await;
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc, 30 * USDC);
+    assert_eq!(user.deposited_sol, 13 * SOL / 10);
+    assert_eq!(user.borrowed_value, 30 * USDC);
+
+    let usdc_bank: Bank = market.account(bank_address(&market.usdc_mint)).await;
+    assert_eq!(usdc_bank.total_borrowed, 30 * USDC);
+}
+
+#[tokio::test]
+async fn leverage_pays_the_attributed_referrer() {
+    let mut market = Market::new().await;
+    market
+        .update_bank(
+            market.usdc_mint,
+            UpdateBankParams {
+                borrow_fee_bps: Some(100),
+                host_fee_bps: Some(2000),
+                ..Default::default()
+            },
+        )
+        .await;
+    let referrer = Pubkey::new_unique();
+    for mint in [market.sol_mint, market.usdc_mint] {
+        market.init_referrer(referrer, mint).await;
+    }
+
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market
+        .deposit(&wallet, market.sol_mint, SOL, Some(referrer))
+        .await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+
+    // The deposit attributed the user, so a fee-bearing borrow must pay them.
+    let error = market
+        .leverage(
+            &wallet,
+            market.usdc_mint,
+            market.sol_mint,
+            50 * USDC,
+            0,
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::ReferrerRequired.into())
+        )
+    );
+    market
+        .leverage(
+            &wallet,
+            market.usdc_mint,
+            market.sol_mint,
+            50 * USDC,
+            0,
+            Some(referrer),
+        )
+        .await
+        .unwrap();
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc, 50 * USDC + USDC / 2);
+
+    let account: Referrer = market
+        .account(referrer_address(&referrer, &market.usdc_mint))
+        .await;
+    assert_eq!(account.claimable, USDC / 10);
+    let usdc_bank: Bank = market.account(bank_address(&market.usdc_mint)).await;
+    assert_eq!(usdc_bank.referrer_fees, USDC / 10);
+    assert_eq!(usdc_bank.protocol_fees, 2 * USDC / 5);
+}
+
+#[tokio::test]
+async fn deposit_receipts_stay_in_the_wallet_until_locked() {
+    let mut market = Market::new().await;
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, SOL, None).await;
+
+    let receipt_ata = receipt_ata_address(&wallet.pubkey(), &market.sol_mint);
+    assert_eq!(market.token_balance(receipt_ata).await, SOL);
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.deposited_sol_shares, 0);
+
+    market
+        .redeem_receipt(&wallet, market.sol_mint, u64::MAX)
+        .await;
+
+    assert_eq!(market.token_balance(receipt_ata).await, 0);
+    assert_eq!(
+        market
+            .token_balance(get_associated_token_address(
+                &wallet.pubkey(),
+                &market.sol_mint
+            ))
+            .await,
+        SOL
+    );
+    let sol_bank: Bank = market.account(bank_address(&market.sol_mint)).await;
+    assert_eq!(sol_bank.total_deposit, 0);
+    assert_eq!(sol_bank.total_deposit_shares, 0);
+}
+
+#[tokio::test]
+async fn receipts_from_an_unrelated_bank_cannot_back_the_position() {
+    let mut market = Market::new().await;
+    let other_mint = market.new_mint(9).await;
+    market.init_bank(other_mint).await;
+
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, other_mint, SOL, None).await;
+
+    let instruction = market.lock_receipts_instruction(&wallet, other_mint).await;
+    let error = market
+        .try_process(instruction, &[&wallet])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::UnsupportedMint.into())
+        )
+    );
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.deposited_sol_shares, 0);
+}
+
+#[tokio::test]
+async fn only_the_market_admin_sets_a_bank_oracle() {
+    let mut market = Market::new().await;
+    let other_mint = market.new_mint(9).await;
+    market.init_bank(other_mint).await;
+
+    let outsider = market.new_wallet().await;
+    let instruction =
+        market.set_bank_oracle_instruction(&outsider.pubkey(), other_mint, SOL_USD_FEED_ID);
+    let error = market
+        .try_process(instruction, &[&outsider])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::NotMarketAdmin.into())
+        )
+    );
+
+    let bank: Bank = market.account(bank_address(&other_mint)).await;
+    assert_eq!(bank.oracle_feed_id, [0; 32]);
+}
+
+#[tokio::test]
+async fn supply_rewards_go_to_collateral_not_wallet_receipts() {
+    let mut market = Market::new().await;
+    // The lender's 1000 USDC sits in its wallet as receipts; this user locks 100.
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market
+        .deposit(&wallet, market.usdc_mint, 100 * USDC, None)
+        .await;
+
+    let reward_mint = market.new_mint(6).await;
+    let pool = market
+        .init_reward_pool(market.usdc_mint, reward_mint, 1_000 * USDC, USDC)
+        .await;
+    market
+        .init_user_reward(&wallet, market.usdc_mint, pool)
+        .await;
+    let user = user_address(&wallet.pubkey());
+    let mut instruction = market
+        .lock_receipts_instruction(&wallet, market.usdc_mint)
+        .await;
+    instruction.accounts.extend([
+        AccountMeta::new(pool, false),
+        AccountMeta::new(user_reward_address(&pool, &user), false),
+    ]);
+    market.process(instruction, &[&wallet]).await;
+
+    market.warp(100).await;
+    market
+        .claim_rewards(&wallet, market.usdc_mint, reward_mint)
+        .await;
+
+    assert_eq!(
+        market
+            .token_balance(get_associated_token_address(&wallet.pubkey(), &reward_mint))
+            .await,
+        100 * USDC
+    );
+    let usdc_bank: Bank = market.account(bank_address(&market.usdc_mint)).await;
+    assert_eq!(usdc_bank.collateral_shares, 100 * USDC);
+}
+
+/// Deposits `sol` lamports of collateral for a new user, borrows `usdc` micro-USDC
+/// against it and drops SOL to `sol_price` dollars.
+async fn unhealthy_borrower(market: &mut Market, sol: u64, usdc: u64, sol_price: i64) -> Keypair {
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, sol, None).await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+    market.borrow(&wallet, market.usdc_mint, usdc).await;
+    market
+        .set_price(market.sol_price, SOL_USD_FEED_ID, sol_price)
+        .await;
+    wallet
+}
+
+#[tokio::test]
+async fn liquidation_is_capped_by_the_close_factor() {
+    let mut market = Market::new().await;
+    market
+        .update_bank(
+            market.sol_mint,
+            UpdateBankParams {
+                liquidation_fee: Some(2000),
+                insurance_fee: Some(5000),
+                ..Default::default()
+            },
+        )
+        .await;
+    // 1 SOL backing 70 USDC is unhealthy once SOL is worth $80 (80% of $80 < $70).
+    let wallet = unhealthy_borrower(&mut market, SOL, 70 * USDC, 80).await;
+    let liquidator = market.new_wallet().await;
+    market
+        .fund(&liquidator.pubkey(), market.usdc_mint, 100 * USDC)
+        .await;
+
+    market
+        .liquidate(&liquidator, &wallet.pubkey(), u64::MAX)
+        .await
+        .unwrap();
+
+    // Half the debt is repaid: $35 buys $38.50 of SOL at $80, 0.48125 SOL.
+    // Of its 0.04375 SOL bonus, 20% goes to the protocol, split evenly
+    // between the fee and insurance vaults.
+    let liquidator_usdc = get_associated_token_address(&liquidator.pubkey(), &market.usdc_mint);
+    assert_eq!(market.token_balance(liquidator_usdc).await, 65 * USDC);
+    let liquidator_sol = get_associated_token_address(&liquidator.pubkey(), &market.sol_mint);
+    assert_eq!(market.token_balance(liquidator_sol).await, 472_500_000);
+    assert_eq!(
+        market
+            .token_balance(fee_vault_address(&market.sol_mint))
+            .await,
+        4_375_000
+    );
+    assert_eq!(
+        market
+            .token_balance(insurance_vault_address(&market.sol_mint))
+            .await,
+        4_375_000
+    );
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc_shares, 35 * USDC);
+    assert_eq!(user.deposited_sol_shares, SOL - 481_250_000);
+}
+
+#[tokio::test]
+async fn liquidation_closes_debt_that_would_leave_dust() {
+    let mut market = Market::new().await;
+    // 0.02 SOL backing 1.50 USDC; half of it would leave $0.75 behind.
+    let wallet = unhealthy_borrower(&mut market, SOL / 50, 3 * USDC / 2, 90).await;
+    let liquidator = market.new_wallet().await;
+    market
+        .fund(&liquidator.pubkey(), market.usdc_mint, 10 * USDC)
+        .await;
+
+    market
+        .liquidate(&liquidator, &wallet.pubkey(), u64::MAX)
+        .await
+        .unwrap();
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc_shares, 0);
+    // $1.65 of SOL at $90, rounded down.
+    let liquidator_sol = get_associated_token_address(&liquidator.pubkey(), &market.sol_mint);
+    assert_eq!(market.token_balance(liquidator_sol).await, 18_333_333);
+    assert_eq!(user.deposited_sol_shares, SOL / 50 - 18_333_333);
+}
+
+#[tokio::test]
+async fn healthy_positions_cannot_be_liquidated() {
+    let mut market = Market::new().await;
+    let wallet = unhealthy_borrower(&mut market, SOL, 70 * USDC, 100).await;
+    let liquidator = market.new_wallet().await;
+    market
+        .fund(&liquidator.pubkey(), market.usdc_mint, 100 * USDC)
+        .await;
+
+    let error = market
+        .liquidate(&liquidator, &wallet.pubkey(), u64::MAX)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::NotUndercollateralized.into())
+        )
+    );
+}
+
+#[tokio::test]
+async fn socialized_losses_reach_depositors_last() {
+    let mut market = Market::new().await;
+    market
+        .update_bank(
+            market.usdc_mint,
+            UpdateBankParams {
+                borrow_fee_bps: Some(100),
+                ..Default::default()
+            },
+        )
+        .await;
+    // SOL crashes to $10, so liquidating takes all the collateral and still
+    // leaves most of the 70.70 USDC debt behind.
+    let wallet = unhealthy_borrower(&mut market, SOL, 70 * USDC, 10).await;
+    let liquidator = market.new_wallet().await;
+    market
+        .fund(&liquidator.pubkey(), market.usdc_mint, 100 * USDC)
+        .await;
+    market
+        .liquidate(&liquidator, &wallet.pubkey(), u64::MAX)
+        .await
+        .unwrap();
+
+    let usdc_mint = market.usdc_mint;
+    market
+        .mint_to(insurance_vault_address(&usdc_mint), usdc_mint, 20 * USDC)
+        .await;
+    market
+        .mint_to(fee_vault_address(&usdc_mint), usdc_mint, 10 * USDC)
+        .await;
+
+    let instruction = market.socialize_loss_instruction(&liquidator.pubkey(), &wallet.pubkey());
+    let error = market
+        .try_process(instruction, &[&liquidator])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::Unauthorized.into())
+        )
+    );
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.deposited_sol_shares, 0);
+    let debt = user.borrowed_usdc_shares;
+    let before: Bank = market.account(bank_address(&usdc_mint)).await;
+    assert_eq!(before.protocol_fees, 7 * USDC / 10);
+    let treasury_before = market.token_balance(treasury_address(&usdc_mint)).await;
+
+    let instruction =
+        market.socialize_loss_instruction(&market.context.payer.pubkey(), &wallet.pubkey());
+    market.process(instruction, &[]).await;
+
+    // 20 USDC of insurance, then 0.70 of unswept fees and the 10 in the fee
+    // vault, cover the debt before depositors take the rest.
+    let after: Bank = market.account(bank_address(&usdc_mint)).await;
+    assert_eq!(after.protocol_fees, 0);
+    assert_eq!(
+        before.total_deposit - after.total_deposit,
+        debt - 20 * USDC - 7 * USDC / 10 - 10 * USDC
+    );
+    assert_eq!(
+        market
+            .token_balance(insurance_vault_address(&usdc_mint))
+            .await,
+        0
+    );
+    assert_eq!(market.token_balance(fee_vault_address(&usdc_mint)).await, 0);
+    assert_eq!(
+        market.token_balance(treasury_address(&usdc_mint)).await,
+        treasury_before + 30 * USDC
+    );
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc_shares, 0);
+}
+
+#[tokio::test]
+async fn insurance_withdrawals_wait_out_the_timelock() {
+    let mut market = Market::new().await;
+    let usdc_mint = market.usdc_mint;
+    market
+        .mint_to(insurance_vault_address(&usdc_mint), usdc_mint, 50 * USDC)
+        .await;
+    let admin = market.context.payer.pubkey();
+    let outsider = market.new_wallet().await;
+
+    for instruction in [
+        market.request_insurance_withdrawal_instruction(&outsider.pubkey(), usdc_mint, 50 * USDC),
+        market.withdraw_insurance_instruction(&outsider.pubkey(), usdc_mint),
+    ] {
+        let error = market
+            .try_process(instruction, &[&outsider])
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(ErrorCode::Unauthorized.into())
+            )
+        );
+    }
+
+    let instruction = market.request_insurance_withdrawal_instruction(&admin, usdc_mint, 50 * USDC);
+    market.process(instruction, &[]).await;
+
+    market.warp(INSURANCE_WITHDRAWAL_TIMELOCK - 1).await;
+    let instruction = market.withdraw_insurance_instruction(&admin, usdc_mint);
+    let error = market
+        .try_process(instruction, &[])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::InsuranceWithdrawalLocked.into())
+        )
+    );
+
+    market.warp(1).await;
+    let instruction = market.withdraw_insurance_instruction(&admin, usdc_mint);
+    market.process(instruction, &[]).await;
+
+    assert_eq!(
+        market
+            .token_balance(get_associated_token_address(&admin, &usdc_mint))
+            .await,
+        50 * USDC
+    );
+    assert_eq!(
+        market
+            .token_balance(insurance_vault_address(&usdc_mint))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn isolated_collateral_is_capped_by_the_debt_ceiling() {
+    let mut market = Market::new().await;
+    market
+        .update_bank(
+            market.sol_mint,
+            UpdateBankParams {
+                risk_tier: Some(RiskTier::Isolated),
+                debt_ceiling: Some(50 * USDC),
+                ..Default::default()
+            },
+        )
+        .await;
+    let mut borrowers = Vec::new();
+    for _ in 0..2 {
+        let wallet = market.new_wallet().await;
+        market.init_user(&wallet).await;
+        market.deposit(&wallet, market.sol_mint, SOL, None).await;
+        market.lock_receipts(&wallet, market.sol_mint).await;
+        borrowers.push(wallet);
+    }
+
+    let error = market
+        .try_borrow(&borrowers[0], market.usdc_mint, 10 * USDC, None)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(ErrorCode::NotBorrowableInIsolation.into())
+        )
+    );
+
+    market
+        .update_bank(
+            market.usdc_mint,
+            UpdateBankParams {
+                borrowable_in_isolation: Some(true),
+                ..Default::default()
+            },
+        )
+        .await;
+    market
+        .borrow(&borrowers[0], market.usdc_mint, 40 * USDC)
+        .await;
+
+    // The ceiling is shared, so the second borrower only has 10 USDC left.
+    let error = market
+        .try_borrow(&borrowers[1], market.usdc_mint, 20 * USDC, None)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(ErrorCode::DebtCeilingExceeded.into())
+        )
+    );
+    market
+        .borrow(&borrowers[1], market.usdc_mint, 10 * USDC)
+        .await;
+
+    let sol_bank: Bank = market.account(bank_address(&market.sol_mint)).await;
+    assert_eq!(sol_bank.isolated_debt, 50 * USDC);
+    let user: User = market.account(user_address(&borrowers[0].pubkey())).await;
+    assert_eq!(user.isolated_collateral, market.sol_mint);
+    assert_eq!(user.isolated_debt, 40 * USDC);
+
+    // Liquidating half the first borrower's debt frees that much of the ceiling.
+    market
+        .set_price(market.sol_price, SOL_USD_FEED_ID, 45)
+        .await;
+    let liquidator = market.new_wallet().await;
+    market
+        .fund(&liquidator.pubkey(), market.usdc_mint, 100 * USDC)
+        .await;
+    market
+        .liquidate(&liquidator, &borrowers[0].pubkey(), u64::MAX)
+        .await
+        .unwrap();
+
+    let sol_bank: Bank = market.account(bank_address(&market.sol_mint)).await;
+    assert_eq!(sol_bank.isolated_debt, 30 * USDC);
+    let user: User = market.account(user_address(&borrowers[0].pubkey())).await;
+    assert_eq!(user.isolated_debt, 20 * USDC);
+}
+
+#[tokio::test]
+async fn emode_parameters_apply_only_when_every_bank_is_in_the_category() {
+    let mut market = Market::new().await;
+    let outsider = market.new_wallet().await;
+    let instruction = market.init_emode_category_instruction(&outsider.pubkey(), 1, 9000, 9500);
+    let error = market
+        .try_process(instruction, &[&outsider])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::NotMarketAdmin.into())
+        )
+    );
+
+    let instruction =
+        market.init_emode_category_instruction(&market.context.payer.pubkey(), 1, 9000, 9500);
+    market.process(instruction, &[]).await;
+    market.set_bank_emode(market.sol_mint, 1).await;
+
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, SOL, None).await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+    market.set_emode(&wallet, 1).await;
+
+    // USDC is not in the category yet, so the SOL bank's 75% LTV still applies.
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.allowed_borrow_value, 75 * USDC);
+    let error = market
+        .try_borrow(&wallet, market.usdc_mint, 85 * USDC, None)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(ErrorCode::OverBorrowableAmount.into())
+        )
+    );
+
+    market.set_bank_emode(market.usdc_mint, 1).await;
+    market.borrow(&wallet, market.usdc_mint, 85 * USDC).await;
+
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.allowed_borrow_value, 90 * USDC);
+    assert_eq!(user.unhealthy_borrow_value, 95 * USDC);
+}
+
+#[tokio::test]
+async fn borrow_fees_are_split_with_the_referrer() {
+    let mut market = Market::new().await;
+    market
+        .update_bank(
+            market.usdc_mint,
+            UpdateBankParams {
+                borrow_fee_bps: Some(100),
+                host_fee_bps: Some(2000),
+                ..Default::default()
+            },
+        )
+        .await;
+    let referrer = market.new_wallet().await;
+    for mint in [market.sol_mint, market.usdc_mint] {
+        market.init_referrer(referrer.pubkey(), mint).await;
+    }
+
+    let referred = market.new_wallet().await;
+    let unreferred = market.new_wallet().await;
+    for (wallet, referrer) in [(&referred, Some(referrer.pubkey())), (&unreferred, None)] {
+        market.init_user(wallet).await;
+        market.deposit(wallet, market.sol_mint, SOL, referrer).await;
+        market.lock_receipts(wallet, market.sol_mint).await;
+    }
+
+    market
+        .try_borrow(
+            &referred,
+            market.usdc_mint,
+            50 * USDC,
+            Some(referrer.pubkey()),
+        )
+        .await
+        .unwrap();
+    market
+        .borrow(&unreferred, market.usdc_mint, 50 * USDC)
+        .await;
+
+    // Each borrow adds a 0.50 USDC fee to the debt. The referrer gets 20% of
+    // the referred user's; the protocol keeps the rest of both.
+    let user: User = market.account(user_address(&referred.pubkey())).await;
+    assert_eq!(user.borrowed_usdc, 50 * USDC + USDC / 2);
+    let usdc_bank: Bank = market.account(bank_address(&market.usdc_mint)).await;
+    assert_eq!(usdc_bank.referrer_fees, USDC / 10);
+    assert_eq!(usdc_bank.protocol_fees, 9 * USDC / 10);
+
+    market
+        .claim_referrer_fees(&referrer, market.usdc_mint)
+        .await;
+    assert_eq!(
+        market
+            .token_balance(get_associated_token_address(
+                &referrer.pubkey(),
+                &market.usdc_mint
+            ))
+            .await,
+        USDC / 10
+    );
+    let account: Referrer = market
+        .account(referrer_address(&referrer.pubkey(), &market.usdc_mint))
+        .await;
+    assert_eq!(account.claimable, 0);
+    assert_eq!(account.total_earned, USDC / 10);
+    let usdc_bank: Bank = market.account(bank_address(&market.usdc_mint)).await;
+    assert_eq!(usdc_bank.referrer_fees, 0);
+}
+
+#[tokio::test]
+async fn closing_a_user_closes_its_reward_accounts() {
+    let mut market = Market::new().await;
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market
+        .deposit(&wallet, market.usdc_mint, 100 * USDC, None)
+        .await;
+
+    let reward_mint = market.new_mint(6).await;
+    let pool = market
+        .init_reward_pool(market.usdc_mint, reward_mint, 1_000 * USDC, USDC)
+        .await;
+    market
+        .init_user_reward(&wallet, market.usdc_mint, pool)
+        .await;
+    let user = user_address(&wallet.pubkey());
+    let user_reward = user_reward_address(&pool, &user);
+    let reward_accounts = [
+        AccountMeta::new(pool, false),
+        AccountMeta::new(user_reward, false),
+    ];
+
+    let mut instruction = market
+        .lock_receipts_instruction(&wallet, market.usdc_mint)
+        .await;
+    instruction.accounts.extend(reward_accounts.clone());
+    market.process(instruction, &[&wallet]).await;
+    market.warp(100).await;
+    let mut instruction = market.unlock_receipts_instruction(&wallet, market.usdc_mint, 100 * USDC);
+    instruction.accounts.extend(reward_accounts);
+    let refresh = market.refresh_user_instruction(&wallet.pubkey()).await;
+    market
+        .try_process_all(&[refresh, instruction], &[&wallet])
+        .await
+        .unwrap();
+
+    for (user_rewards, error) in [
+        (vec![], ErrorCode::InvalidRewardAccounts),
+        (vec![user_reward], ErrorCode::UnclaimedRewards),
+    ] {
+        let instruction = market.close_user_instruction(&wallet, &user_rewards);
+        let result = market
+            .try_process(instruction, &[&wallet])
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            result,
+            TransactionError::InstructionError(0, InstructionError::Custom(error.into()))
+        );
+    }
+
+    market
+        .claim_rewards(&wallet, market.usdc_mint, reward_mint)
+        .await;
+    let instruction = market.close_user_instruction(&wallet, &[user_reward]);
+    market.process(instruction, &[&wallet]).await;
+
+    for address in [user, user_reward] {
+        assert!(market
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
+
+#[tokio::test]
+async fn third_parties_repay_only_while_the_owner_allows_it() {
+    let mut market = Market::new().await;
+    let wallet = market.new_wallet().await;
+    market.init_user(&wallet).await;
+    market.deposit(&wallet, market.sol_mint, SOL, None).await;
+    market.lock_receipts(&wallet, market.sol_mint).await;
+    market.borrow(&wallet, market.usdc_mint, 50 * USDC).await;
+    let friend = market.new_wallet().await;
+    market
+        .fund(&friend.pubkey(), market.usdc_mint, 100 * USDC)
+        .await;
+
+    let instruction =
+        market.repay_obligation_instruction(&friend.pubkey(), &wallet.pubkey(), 20 * USDC);
+    market.process(instruction, &[&friend]).await;
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc_shares, 30 * USDC);
+
+    market.set_third_party_repays(&wallet, false).await;
+    let instruction =
+        market.repay_obligation_instruction(&friend.pubkey(), &wallet.pubkey(), 20 * USDC);
+    let error = market
+        .try_process(instruction, &[&friend])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        error,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::ThirdPartyRepaysDisabled.into())
+        )
+    );
+    let user: User = market.account(user_address(&wallet.pubkey())).await;
+    assert_eq!(user.borrowed_usdc_shares, 30 * USDC);
+}
@@ -0,0 +1,176 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+declare_id!("2TdszVnzMjXjdDCn8YkcrvKHZyxjDwoDkdrktRadP6J7");
+
+pub const SEED_POOL: &[u8] = b"pool";
+pub const SEED_VAULT: &[u8] = b"vault";
+
+/// A one-way pool that swaps `mint_in` for `mint_out` at a fixed rate. It
+/// stands in for a DEX when testing the lending program's swap CPIs; fund
+/// `vault_out` directly to give it liquidity.
+#[program]
+pub mod mock_swap {
+    use super::*;
+
+    pub fn init_pool(
+        ctx: Context<InitPool>,
+        rate_numerator: u64,
+        rate_denominator: u64,
+    ) -> Result<()> {
+        if rate_denominator == 0 {
+            return Err(ErrorCode::InvalidRate.into());
+        }
+
+        ctx.accounts.pool.set_inner(Pool {
+            mint_in: ctx.accounts.mint_in.key(),
+            mint_out: ctx.accounts.mint_out.key(),
+            rate_numerator,
+            rate_denominator,
+            bump: ctx.bumps.pool,
+        });
+
+        Ok(())
+    }
+
+    /// Takes `amount_in` of `mint_in` and pays out
+    /// `amount_in * rate_numerator / rate_denominator` base units of `mint_out`.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let amount_out = (amount_in as u128 * pool.rate_numerator as u128
+            / pool.rate_denominator as u128)
+            .try_into()
+            .map_err(|_| ErrorCode::MathOverflow)?;
+
+        if amount_out < min_amount_out {
+            return Err(ErrorCode::SlippageExceeded.into());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.source.to_account_info(),
+            mint: ctx.accounts.mint_in.to_account_info(),
+            to: ctx.accounts.vault_in.to_account_info(),
+            authority: ctx.accounts.signer.to_account_info(),
+        };
+        transfer_checked(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts),
+            amount_in,
+            ctx.accounts.mint_in.decimals,
+        )?;
+
+        let seeds = &[
+            SEED_POOL,
+            pool.mint_in.as_ref(),
+            pool.mint_out.as_ref(),
+            &[pool.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.vault_out.to_account_info(),
+            mint: ctx.accounts.mint_out.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            ),
+            amount_out,
+            ctx.accounts.mint_out.decimals,
+        )
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub rate_numerator: u64,
+    pub rate_denominator: u64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct InitPool<'info> {
+    #[account(mut)]
+    signer: Signer<'info>,
+    mint_in: InterfaceAccount<'info, Mint>,
+    mint_out: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [SEED_POOL, mint_in.key().as_ref(), mint_out.key().as_ref()],
+        bump,
+    )]
+    pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_VAULT, pool.key().as_ref(), mint_in.key().as_ref()],
+        bump,
+        token::mint = mint_in,
+        token::authority = pool,
+        token::token_program = token_program,
+    )]
+    vault_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = signer,
+        seeds = [SEED_VAULT, pool.key().as_ref(), mint_out.key().as_ref()],
+        bump,
+        token::mint = mint_out,
+        token::authority = pool,
+        token::token_program = token_program,
+    )]
+    vault_out: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    signer: Signer<'info>,
+    #[account(
+        seeds = [SEED_POOL, mint_in.key().as_ref(), mint_out.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pool: Account<'info, Pool>,
+    mint_in: InterfaceAccount<'info, Mint>,
+    mint_out: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [SEED_VAULT, pool.key().as_ref(), mint_in.key().as_ref()],
+        bump,
+    )]
+    vault_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [SEED_VAULT, pool.key().as_ref(), mint_out.key().as_ref()],
+        bump,
+    )]
+    vault_out: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = mint_in)]
+    source: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = mint_out)]
+    destination: InterfaceAccount<'info, TokenAccount>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Rate denominator must be non-zero")]
+    InvalidRate,
+    #[msg("Swap output is below the minimum")]
+    SlippageExceeded,
+    #[msg("Math overflow")]
+    MathOverflow,
+}